@@ -11,8 +11,12 @@ pub enum Error {
     OutputFinish(String),
     FileFormat,
     InvalidHash(String),
+    UnknownDigest(usize),
+    Unsupported(String),
     ReadLine(io::Error),
     AuditEmptyDir(String),
+    OutsideBase((String, String)),
+    Glob(String),
 }
 
 impl Debug for Error {
@@ -39,11 +43,20 @@ impl Display for Error {
             ),
             Self::FileFormat => write!(f, "invalid hashes file format"),
             Self::InvalidHash(value) => write!(f, "{value}"),
+            Self::UnknownDigest(len) => write!(
+                f,
+                "unable to infer the hash algorithm of a {len} characters long digest, use the --algo option"
+            ),
+            Self::Unsupported(reason) => write!(f, "unsupported operation: {reason}"),
             Self::ReadLine(error) => write!(f, "failed to read hashes file: {error}"),
             Self::AuditEmptyDir(path) => write!(
                 f,
                 "empty directory: {path}\n - Because the hashes file was created with `empty-dirs` option enabled, this option must also be enabled when auditing"
             ),
+            Self::OutsideBase((path, base)) => {
+                write!(f, "`{path}` is not inside the base directory `{base}`")
+            }
+            Self::Glob(reason) => write!(f, "invalid glob pattern: {reason}"),
         }
     }
 }
@@ -0,0 +1,295 @@
+use std::{
+    fmt::Display,
+    fs::{FileType, Metadata},
+    str::FromStr,
+};
+
+use jiff::Timestamp;
+
+use super::{FileTimes, HashData};
+
+/// Attribute that can be recorded next to the hashes of each native hashes file entry.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Attr {
+    /// Size in bytes, compared along with the content rather than as metadata.
+    Size,
+    Mtime,
+    /// Inode change time.
+    Ctime,
+    /// Permission bits, unix only.
+    Mode,
+    /// Owner user id, unix only.
+    Uid,
+    /// Owner group id, unix only.
+    Gid,
+    Type,
+    /// Hardlink set of files with several links, checked by audit as a whole since the
+    /// device and inode recorded to identify it change when the files are copied.
+    Link,
+}
+
+impl Attr {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Attr::Size => "size",
+            Attr::Mtime => "mtime",
+            Attr::Ctime => "ctime",
+            Attr::Mode => "mode",
+            Attr::Uid => "uid",
+            Attr::Gid => "gid",
+            Attr::Type => "type",
+            Attr::Link => "link",
+        }
+    }
+}
+
+impl FromStr for Attr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "size" => Ok(Attr::Size),
+            "mtime" => Ok(Attr::Mtime),
+            "ctime" => Ok(Attr::Ctime),
+            "mode" => Ok(Attr::Mode),
+            "uid" => Ok(Attr::Uid),
+            "gid" => Ok(Attr::Gid),
+            "type" => Ok(Attr::Type),
+            "link" => Ok(Attr::Link),
+            _ => Err(format!(
+                "invalid attribute: {s}, possible options are: size, mtime, ctime, mode, uid, gid, type, link"
+            )),
+        }
+    }
+}
+
+impl Display for Attr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Device and inode identifying a hardlink set.
+pub type LinkId = (u64, u64);
+
+/// Type of the file a path points to.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+    Other,
+}
+
+impl FileKind {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Dir => "dir",
+            FileKind::Symlink => "symlink",
+            FileKind::Fifo => "fifo",
+            FileKind::Socket => "socket",
+            FileKind::BlockDevice => "block",
+            FileKind::CharDevice => "char",
+            FileKind::Other => "other",
+        }
+    }
+
+    pub fn from_file_type(file_type: FileType) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+
+            if file_type.is_fifo() {
+                return FileKind::Fifo;
+            }
+            if file_type.is_socket() {
+                return FileKind::Socket;
+            }
+            if file_type.is_block_device() {
+                return FileKind::BlockDevice;
+            }
+            if file_type.is_char_device() {
+                return FileKind::CharDevice;
+            }
+        }
+        if file_type.is_file() {
+            FileKind::File
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+impl FromStr for FileKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(FileKind::File),
+            "dir" => Ok(FileKind::Dir),
+            "symlink" => Ok(FileKind::Symlink),
+            "fifo" => Ok(FileKind::Fifo),
+            "socket" => Ok(FileKind::Socket),
+            "block" => Ok(FileKind::BlockDevice),
+            "char" => Ok(FileKind::CharDevice),
+            "other" => Ok(FileKind::Other),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Permissions, ownership and type of a path, each one only present if the platform
+/// provides it.
+#[derive(Clone, Copy, Default)]
+pub struct FileAttrs {
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    kind: Option<FileKind>,
+    /// Device and inode of files with several hardlinks.
+    link: Option<LinkId>,
+}
+
+impl FileAttrs {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self {
+            mode: Some(metadata.mode() & 0o7777),
+            uid: Some(metadata.uid()),
+            gid: Some(metadata.gid()),
+            kind: Some(FileKind::from_file_type(metadata.file_type())),
+            link: link_id(metadata),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            kind: Some(FileKind::from_file_type(metadata.file_type())),
+            ..Default::default()
+        }
+    }
+}
+
+/// Device and inode of a file with several hardlinks, unix only.
+#[cfg(unix)]
+pub fn link_id(metadata: &Metadata) -> Option<LinkId> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.is_file() && metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn link_id(_metadata: &Metadata) -> Option<LinkId> {
+    None
+}
+
+/// Hardlink set `hash_data` belongs to, only recorded for files with several links.
+pub fn link(hash_data: &HashData) -> Option<LinkId> {
+    hash_data.attrs.and_then(|attrs| attrs.link)
+}
+
+/// Formats the values of `attrs` for `hash_data`, comma separated and in order. Values that
+/// are not available are left empty.
+pub fn format(attrs: &[Attr], hash_data: &HashData) -> String {
+    let file_attrs = hash_data.attrs.unwrap_or_default();
+    let times = hash_data.times;
+    let values: Vec<String> = attrs
+        .iter()
+        .map(|attr| match attr {
+            Attr::Size => hash_data.size.map(|size| size.to_string()),
+            Attr::Mtime => times
+                .and_then(|times| times.modified)
+                .map(|time| time.to_string()),
+            Attr::Ctime => times
+                .and_then(|times| times.changed)
+                .map(|time| time.to_string()),
+            Attr::Mode => file_attrs.mode.map(|mode| format!("{mode:o}")),
+            Attr::Uid => file_attrs.uid.map(|uid| uid.to_string()),
+            Attr::Gid => file_attrs.gid.map(|gid| gid.to_string()),
+            Attr::Type => file_attrs.kind.map(|kind| kind.as_str().to_owned()),
+            Attr::Link => file_attrs
+                .link
+                .map(|(device, inode)| format!("{device}:{inode}")),
+        })
+        .map(Option::unwrap_or_default)
+        .collect();
+    values.join(",")
+}
+
+/// Parses the values written by [`format`] into `hash_data`.
+pub fn parse(attrs: &[Attr], values: &str, hash_data: &mut HashData) -> Option<()> {
+    let values: Vec<&str> = values.split(',').collect();
+    if values.len() != attrs.len() {
+        return None;
+    }
+    let mut file_attrs = FileAttrs::default();
+    let mut times = FileTimes::default();
+    for (attr, value) in attrs.iter().zip(values) {
+        if value.is_empty() {
+            continue;
+        }
+        match attr {
+            Attr::Size => hash_data.size = Some(value.parse().ok()?),
+            Attr::Mtime => times.modified = Some(value.parse::<Timestamp>().ok()?),
+            Attr::Ctime => times.changed = Some(value.parse::<Timestamp>().ok()?),
+            Attr::Mode => file_attrs.mode = Some(u32::from_str_radix(value, 8).ok()?),
+            Attr::Uid => file_attrs.uid = Some(value.parse().ok()?),
+            Attr::Gid => file_attrs.gid = Some(value.parse().ok()?),
+            Attr::Type => file_attrs.kind = Some(value.parse().ok()?),
+            Attr::Link => {
+                let (device, inode) = value.split_once(':')?;
+                file_attrs.link = Some((device.parse().ok()?, inode.parse().ok()?));
+            }
+        }
+    }
+    hash_data.attrs = Some(file_attrs);
+    hash_data.times = Some(times);
+    Some(())
+}
+
+/// Metadata attributes recorded in `recorded` that changed in `current`. The size is not
+/// included, it is part of the content comparison, see [`HashData::matches`], nor is the
+/// hardlink set, which is checked across all its files.
+pub fn drift(recorded: &HashData, current: &HashData) -> Vec<Attr> {
+    let (Some(recorded_attrs), Some(recorded_times)) = (recorded.attrs, recorded.times) else {
+        return vec![];
+    };
+    let current_attrs = current.attrs.unwrap_or_default();
+    let current_times = current.times;
+    let mut drift = vec![];
+    if recorded_times.modified.is_some()
+        && recorded_times.modified != current_times.and_then(|times| times.modified)
+    {
+        drift.push(Attr::Mtime);
+    }
+    if recorded_times.changed.is_some()
+        && recorded_times.changed != current_times.and_then(|times| times.changed)
+    {
+        drift.push(Attr::Ctime);
+    }
+    if recorded_attrs.mode.is_some() && recorded_attrs.mode != current_attrs.mode {
+        drift.push(Attr::Mode);
+    }
+    if recorded_attrs.uid.is_some() && recorded_attrs.uid != current_attrs.uid {
+        drift.push(Attr::Uid);
+    }
+    if recorded_attrs.gid.is_some() && recorded_attrs.gid != current_attrs.gid {
+        drift.push(Attr::Gid);
+    }
+    if recorded_attrs.kind.is_some() && recorded_attrs.kind != current_attrs.kind {
+        drift.push(Attr::Type);
+    }
+    drift
+}
@@ -1,20 +1,29 @@
 use std::{
-    collections::VecDeque,
+    borrow::Borrow,
+    collections::{HashMap, HashSet, VecDeque},
     fmt::Display,
     fs::File,
-    io::{BufRead, BufReader, Lines},
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader},
     path::{Path, PathBuf},
     str::FromStr,
     sync::mpsc::{Receiver, Sender},
 };
 
-use jiff::civil::Date;
+use jiff::{Timestamp, civil::Date};
+use serde_json::json;
 
 use crate::{DEFAULT_OUT, Error, HashType, exec::cancel, verbose_print};
 
 use super::{
-    HASH_ALGO_STR, HashData, HashHandler, NO_DATE_STR, TIME_FINISH_STR, TIME_START_STR,
-    VERSION_STR, cancel_on_err, is_canceled, path_string,
+    ATTRS_STR, Attr, BASE_STR, EXCLUDE_STR, FORMAT_STR, FORMAT_VERSION, HASH_ALGO_STR,
+    HASH_BLOCK_DEVICES_STR, HashData, HashHandler, IGNORE_FILES_STR, INCLUDE_STR, NO_DATE_STR,
+    NON_CRYPTO_STR, ONE_FILE_SYSTEM_STR, Patterns, Precheck, SPECIAL_FILES_STR, SYMLINKS_STR,
+    SpecialFiles, Symlinks, TIME_FINISH_STR, TIME_START_STR, VERSION_STR,
+    attrs::{self, LinkId},
+    cancel_on_err,
+    format::{self, Format, HashValues},
+    is_canceled, path_string, unescape_path,
 };
 
 enum AuditError {
@@ -22,6 +31,10 @@ enum AuditError {
     Mismatch(String),
     Extra(String),
     EmptyDir(String),
+    /// The content matches but recorded attributes changed.
+    Drift(String, Vec<Attr>),
+    /// The file is no longer a hardlink of the first file of its recorded hardlink set.
+    Unlinked(String, String),
 }
 
 impl Display for AuditError {
@@ -35,30 +48,578 @@ impl Display for AuditError {
             AuditError::EmptyDir(path) => {
                 write!(f, "audit_err: directory \"{path}\" should not be empty")
             }
+            AuditError::Drift(path, attrs) => {
+                let attrs: Vec<&str> = attrs.iter().map(Attr::as_str).collect();
+                write!(
+                    f,
+                    "audit_err: \"{path}\" metadata changed: {}",
+                    attrs.join(", ")
+                )
+            }
+            AuditError::Unlinked(path, first) => write!(
+                f,
+                "audit_err: \"{path}\" is no longer a hardlink of \"{first}\""
+            ),
+        }
+    }
+}
+
+/// Number of reported audit errors, by kind.
+#[derive(Default, Clone, Copy)]
+pub struct AuditCounts {
+    pub not_found: usize,
+    pub mismatch: usize,
+    pub extra: usize,
+    pub empty_dir: usize,
+    pub drift: usize,
+    pub unlinked: usize,
+}
+
+impl AuditCounts {
+    pub fn total(&self) -> usize {
+        self.not_found + self.mismatch + self.extra + self.empty_dir + self.drift + self.unlinked
+    }
+
+    fn count(&mut self, audit_err: &AuditError) {
+        match audit_err {
+            AuditError::NotFound(_) => self.not_found += 1,
+            AuditError::Mismatch(_) => self.mismatch += 1,
+            AuditError::Extra(_) => self.extra += 1,
+            AuditError::EmptyDir(_) => self.empty_dir += 1,
+            AuditError::Drift(..) => self.drift += 1,
+            AuditError::Unlinked(..) => self.unlinked += 1,
         }
     }
 }
 
+/// Result of an audit that ran to completion.
+pub enum AuditOutcome {
+    /// Every audited path matched the hashes file.
+    Clean,
+    /// At least one discrepancy was found.
+    Discrepancies(AuditCounts),
+}
+
+impl AuditOutcome {
+    /// JSON object summarizing the audit, printed last in json report mode.
+    pub fn to_json(&self) -> serde_json::Value {
+        let (result, counts) = match self {
+            AuditOutcome::Clean => ("clean", AuditCounts::default()),
+            AuditOutcome::Discrepancies(counts) => ("discrepancies", *counts),
+        };
+        json!({
+            "event": "summary",
+            "result": result,
+            "not_found": counts.not_found,
+            "mismatch": counts.mismatch,
+            "extra": counts.extra,
+            "empty_dir": counts.empty_dir,
+            "drift": counts.drift,
+            "unlinked": counts.unlinked,
+            "total": counts.total(),
+        })
+    }
+}
+
 impl AuditError {
-    fn print_and_cancel(&self, early: bool) {
-        verbose_print(|| self, false);
+    fn kind(&self) -> &str {
+        match self {
+            AuditError::NotFound(_) => "not_found",
+            AuditError::Mismatch(_) => "mismatch",
+            AuditError::Extra(_) => "extra",
+            AuditError::EmptyDir(_) => "empty_dir",
+            AuditError::Drift(..) => "drift",
+            AuditError::Unlinked(..) => "unlinked",
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            AuditError::NotFound(path)
+            | AuditError::Mismatch(path)
+            | AuditError::Extra(path)
+            | AuditError::EmptyDir(path)
+            | AuditError::Drift(path, _)
+            | AuditError::Unlinked(path, _) => path,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut value = json!({
+            "event": "audit_err",
+            "kind": self.kind(),
+            "path": self.path(),
+        });
+        match self {
+            AuditError::Drift(_, attrs) => {
+                let attrs: Vec<&str> = attrs.iter().map(Attr::as_str).collect();
+                value["attrs"] = json!(attrs);
+            }
+            AuditError::Unlinked(_, first) => value["link"] = json!(first),
+            _ => (),
+        }
+        value
+    }
+
+    fn print_and_cancel(&self, early: bool, json: bool) {
+        if json {
+            // Printed as is, a verbose thread prefix would not be valid JSON Lines.
+            println!("{}", self.to_json());
+        } else {
+            verbose_print(|| self, false);
+        }
         if early {
             cancel();
         }
     }
 }
 
-type HashesFile = Lines<BufReader<File>>;
+/// Lines of a hashes file. They are read as bytes, coreutils and BSD files hold the paths as
+/// raw bytes that need not be valid UTF-8.
+struct HashesLines {
+    reader: Box<dyn BufRead>,
+    peeked: Option<Vec<u8>>,
+}
+
+impl HashesLines {
+    fn new(reader: Box<dyn BufRead>) -> Self {
+        Self {
+            reader,
+            peeked: None,
+        }
+    }
+
+    /// Next line, along with its terminator.
+    fn next_raw(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(line) = self.peeked.take() {
+            return Ok(Some(line));
+        }
+        let mut line = vec![];
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line)),
+            Err(err) => Err(Error::ReadLine(err)),
+        }
+    }
+
+    /// Next line without its terminator.
+    fn next_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let Some(mut line) = self.next_raw()? else {
+            return Ok(None);
+        };
+        line.truncate(strip_terminator(&line).len());
+        Ok(Some(line))
+    }
+
+    /// Next line without its terminator, failing if it is not valid UTF-8.
+    fn next_line(&mut self) -> Result<Option<String>, Error> {
+        let Some(line) = self.next_bytes()? else {
+            return Ok(None);
+        };
+        String::from_utf8(line)
+            .map(Some)
+            .map_err(|_| invalid_utf8())
+    }
+
+    /// Next line without its terminator, which is still returned by the next read.
+    fn peek_bytes(&mut self) -> Result<Option<&[u8]>, Error> {
+        if self.peeked.is_none() {
+            self.peeked = self.next_raw()?;
+        }
+        Ok(self.peeked.as_deref().map(strip_terminator))
+    }
+}
+
+fn invalid_utf8() -> Error {
+    Error::ReadLine(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    ))
+}
+
+/// `line` without its `\n` or `\r\n` terminator.
+fn strip_terminator(line: &[u8]) -> &[u8] {
+    match line.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => line,
+    }
+}
+
+pub struct HashesFile {
+    lines: HashesLines,
+    format: Format,
+    hashes: Vec<HashType>,
+    /// Entries already parsed while detecting the format of the file.
+    pending: VecDeque<HashData>,
+    /// Whether the native entries have escaped paths, from format version 2 on.
+    escaped_paths: bool,
+    /// Attributes recorded after the hashes of every native entry.
+    attrs: Vec<Attr>,
+    /// Directory the native entries were made relative to.
+    base: Option<PathBuf>,
+    /// Traversal filter the native file was created with.
+    patterns: Patterns,
+    /// Symlink policy the native file was created with.
+    symlinks: Option<Symlinks>,
+    /// Whether the native file was created without crossing mount points.
+    one_file_system: bool,
+    /// Special files policy the native file was created with.
+    special_files: Option<SpecialFiles>,
+    /// Whether the native file was created hashing block devices.
+    hash_block_devices: bool,
+}
+
+impl HashesFile {
+    fn new(lines: HashesLines, format: Format, hashes: Vec<HashType>) -> Self {
+        Self {
+            lines,
+            format,
+            hashes,
+            pending: VecDeque::new(),
+            escaped_paths: false,
+            attrs: vec![],
+            base: None,
+            patterns: Patterns::default(),
+            symlinks: None,
+            one_file_system: false,
+            special_files: None,
+            hash_block_devices: false,
+        }
+    }
+
+    /// Base directory recorded in the file, only native files record it.
+    pub fn base(&self) -> Option<&Path> {
+        self.base.as_deref()
+    }
+
+    /// Traversal filter recorded in the file, only native files record it.
+    pub fn patterns(&self) -> &Patterns {
+        &self.patterns
+    }
+
+    /// Symlink policy recorded in the file, only native files record it.
+    pub fn symlinks(&self) -> Option<Symlinks> {
+        self.symlinks
+    }
+
+    /// Whether the file was created without crossing mount points, only native files
+    /// record it.
+    pub fn one_file_system(&self) -> bool {
+        self.one_file_system
+    }
+
+    /// Special files policy recorded in the file, only native files record it.
+    pub fn special_files(&self) -> Option<SpecialFiles> {
+        self.special_files
+    }
+
+    /// Whether the file was created hashing block devices, only native files record it.
+    pub fn hash_block_devices(&self) -> bool {
+        self.hash_block_devices
+    }
+
+    fn next_line(&mut self) -> Result<Option<String>, Error> {
+        self.lines.next_line()
+    }
+
+    fn next_entry(&mut self, empty_dirs: bool) -> Result<Option<HashData>, Error> {
+        if let Some(hash_data) = self.pending.pop_front() {
+            if hash_data.hash().is_none() && !empty_dirs {
+                return Err(Error::AuditEmptyDir(path_string(hash_data.path())));
+            }
+            return Ok(Some(hash_data));
+        }
+        match self.format {
+            Format::Coreutils => return self.next_coreutils_entry(),
+            Format::Bsd => return self.next_bsd_entry(),
+            _ => (),
+        }
+        let line = match self.format {
+            Format::Csv(_) => self.next_csv_record()?,
+            _ => self.next_line()?,
+        };
+        let Some(line) = line else {
+            return Ok(None);
+        };
+        verbose_print(
+            || format!("reading next line from hashes file: {:?}", &line),
+            true,
+        );
+        match self.format {
+            Format::Hashdeep => match format::parse_hashdeep(&line, self.hashes.len()) {
+                Some(hash_data) => Ok(Some(hash_data)),
+                None if line.starts_with(format::HASHDEEP_COMMENT_STR) || line.is_empty() => {
+                    self.next_entry(empty_dirs)
+                }
+                None => Err(Error::FileFormat),
+            },
+            Format::Jsonl => {
+                let parsed = format::parse_jsonl(&line);
+                self.parsed_entry(parsed, &line, empty_dirs)
+            }
+            Format::Csv(ref columns) => {
+                let parsed = format::parse_csv(columns, &line);
+                self.parsed_entry(parsed, &line, empty_dirs)
+            }
+            _ if self.attrs.is_empty() => {
+                HashData::try_from_string(line, empty_dirs, self.escaped_paths).map(Some)
+            }
+            _ => {
+                let (line, values) = line.rsplit_once('|').ok_or(Error::FileFormat)?;
+                let mut hash_data =
+                    HashData::try_from_string(line.to_owned(), empty_dirs, self.escaped_paths)?;
+                attrs::parse(&self.attrs, values, &mut hash_data).ok_or(Error::FileFormat)?;
+                Ok(Some(hash_data))
+            }
+        }
+    }
+
+    /// Reads a CSV record, which spans several lines when a quoted field holds line breaks.
+    /// Those are kept as they are, only the terminator of the record is removed.
+    fn next_csv_record(&mut self) -> Result<Option<String>, Error> {
+        let Some(mut record) = self.lines.next_raw()? else {
+            return Ok(None);
+        };
+        while format::is_csv_record_open(&record) {
+            let next = self.lines.next_raw()?.ok_or(Error::FileFormat)?;
+            record.extend(next);
+        }
+        record.truncate(strip_terminator(&record).len());
+        String::from_utf8(record)
+            .map(Some)
+            .map_err(|_| invalid_utf8())
+    }
+
+    /// Completes an entry of the formats that mark directories and list digests by algorithm.
+    fn parsed_entry(
+        &mut self,
+        parsed: Option<(HashData, Option<HashValues>)>,
+        line: &str,
+        empty_dirs: bool,
+    ) -> Result<Option<HashData>, Error> {
+        match parsed {
+            Some((mut hash_data, Some(values))) => {
+                hash_data.hash = Some(self.join_values(&values)?);
+                Ok(Some(hash_data))
+            }
+            Some((hash_data, None)) if empty_dirs => Ok(Some(hash_data)),
+            Some((hash_data, None)) => Err(Error::AuditEmptyDir(path_string(hash_data.path()))),
+            None if line.trim().is_empty() => self.next_entry(empty_dirs),
+            None => Err(Error::FileFormat),
+        }
+    }
+
+    /// Next line of the coreutils and BSD formats that is neither blank nor a comment.
+    fn next_text_line(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        while let Some(line) = self.lines.next_bytes()? {
+            verbose_print(
+                || {
+                    format!(
+                        "reading next line from hashes file: \"{}\"",
+                        line.escape_ascii()
+                    )
+                },
+                true,
+            );
+            if !is_blank_or_comment(&line) {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_coreutils_entry(&mut self) -> Result<Option<HashData>, Error> {
+        let Some(line) = self.next_text_line()? else {
+            return Ok(None);
+        };
+        let (hash, path) = format::parse_coreutils(&line).ok_or(Error::FileFormat)?;
+        Ok(Some(HashData::with_hash(path, Some(hash))))
+    }
+
+    /// Reads all consecutive BSD lines describing the same path, returning their algorithms
+    /// and digests in the order they appear.
+    fn next_bsd_group(&mut self) -> Result<Option<(PathBuf, HashValues)>, Error> {
+        let Some(line) = self.next_text_line()? else {
+            return Ok(None);
+        };
+        let (hash, path, value) = format::parse_bsd(&line).ok_or(Error::FileFormat)?;
+        let mut values = vec![(hash, value)];
+        while let Some(line) = self.lines.peek_bytes()? {
+            match format::parse_bsd(line) {
+                Some((hash, next_path, value)) if next_path == path => {
+                    values.push((hash, value));
+                    self.lines.next_raw()?;
+                }
+                _ => break,
+            }
+        }
+        Ok(Some((path, values)))
+    }
+
+    fn next_bsd_entry(&mut self) -> Result<Option<HashData>, Error> {
+        let Some((path, values)) = self.next_bsd_group()? else {
+            return Ok(None);
+        };
+        let hash = self.join_values(&values)?;
+        Ok(Some(HashData::with_hash(path, Some(hash))))
+    }
+
+    /// Joins the digests of an entry in the order of the hash algorithms of the file.
+    fn join_values(&self, values: &HashValues) -> Result<String, Error> {
+        let mut hash_list = Vec::with_capacity(self.hashes.len());
+        for hash in &self.hashes {
+            let (_, value) = values
+                .iter()
+                .find(|(value_hash, _)| value_hash == hash)
+                .ok_or(Error::FileFormat)?;
+            hash_list.push(value.as_str());
+        }
+        Ok(hash_list.join(","))
+    }
+
+    /// Reads JSON Lines entries up to the first file, which determines the hash algorithms.
+    fn detect_jsonl_hashes(&mut self) -> Result<(), Error> {
+        while let Some(line) = self.next_line()? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (mut hash_data, values) = format::parse_jsonl(&line).ok_or(Error::FileFormat)?;
+            if let Some(values) = values {
+                self.hashes = values.iter().map(|(hash, _)| hash.clone()).collect();
+                hash_data.hash = Some(self.join_values(&values)?);
+                self.pending.push_back(hash_data);
+                break;
+            }
+            self.pending.push_back(hash_data);
+        }
+        Ok(())
+    }
+}
 
-pub fn load_check_file(path: Option<PathBuf>) -> Result<(HashesFile, Vec<HashType>), Error> {
+fn is_blank_or_comment(line: &[u8]) -> bool {
+    line.trim_ascii().is_empty() || line.starts_with(b"#")
+}
+
+/// Opens a hashes file, detecting whether it is in this program's native format, in the
+/// GNU coreutils format, in the BSD tag format, in the hashdeep format, in JSON Lines or in CSV.
+/// For coreutils files, `algo` selects the hash algorithm, otherwise it is inferred from
+/// the length of the first digest.
+pub fn load_check_file(
+    path: Option<PathBuf>,
+    algo: Option<HashType>,
+) -> Result<(HashesFile, Vec<HashType>), Error> {
     verbose_print(|| "loading check file", true);
     let path = path.unwrap_or(PathBuf::from(DEFAULT_OUT));
     let file = File::open(&path).map_err(|err| Error::Io((err, path_string(&path))))?;
-    let mut lines = BufReader::new(file).lines();
-    match lines.next() {
+    read_check_file(Box::new(BufReader::new(file)), algo)
+}
+
+/// Detects the format of the hashes file read from `reader`, see [`load_check_file`].
+fn read_check_file(
+    reader: Box<dyn BufRead>,
+    algo: Option<HashType>,
+) -> Result<(HashesFile, Vec<HashType>), Error> {
+    let mut lines = HashesLines::new(reader);
+    let first_bytes = lines.peek_bytes()?.ok_or(Error::FileFormat)?.to_vec();
+    // Only the coreutils and BSD formats may hold bytes that are not valid UTF-8.
+    let first = String::from_utf8_lossy(&first_bytes);
+    if first.starts_with(VERSION_STR) {
+        let (hashes, extension) = load_native_header(&mut lines)?;
+        let mut hashes_file = HashesFile::new(lines, Format::Native, vec![]);
+        hashes_file.escaped_paths = extension.version >= 2;
+        hashes_file.attrs = extension.attrs;
+        hashes_file.base = extension.base;
+        hashes_file.patterns = extension.patterns;
+        hashes_file.symlinks = extension.symlinks;
+        hashes_file.one_file_system = extension.one_file_system;
+        hashes_file.special_files = extension.special_files;
+        hashes_file.hash_block_devices = extension.hash_block_devices;
+        return Ok((hashes_file, hashes));
+    }
+    if first.starts_with(format::XML_STR) {
+        return Err(Error::Unsupported(
+            "xml hashes files, such as dfxml, cannot be audited".to_owned(),
+        ));
+    }
+    if first == format::HASHDEEP_STR {
+        verbose_print(|| "hashes file is in hashdeep format", true);
+        lines.next_raw()?;
+        let columns = lines.next_line()?.ok_or(Error::FileFormat)?;
+        let hashes = format::parse_hashdeep_columns(&columns).ok_or(Error::FileFormat)?;
+        return Ok((
+            HashesFile::new(lines, Format::Hashdeep, hashes.clone()),
+            hashes,
+        ));
+    }
+    if first.starts_with(format::JSONL_STR) {
+        verbose_print(|| "hashes file is in jsonl format", true);
+        let mut hashes_file = HashesFile::new(lines, Format::Jsonl, vec![]);
+        hashes_file.detect_jsonl_hashes()?;
+        let hashes = hashes_file.hashes.clone();
+        return Ok((hashes_file, hashes));
+    }
+    if let Some(columns) = format::parse_csv_header(&first) {
+        verbose_print(|| "hashes file is in csv format", true);
+        lines.next_raw()?;
+        let hashes: Vec<HashType> = columns
+            .iter()
+            .filter_map(|column| match column {
+                format::CsvColumn::Hash(hash) => Some(hash.clone()),
+                _ => None,
+            })
+            .collect();
+        return Ok((
+            HashesFile::new(lines, Format::Csv(columns), hashes.clone()),
+            hashes,
+        ));
+    }
+    if let Some((hash, _)) = format::parse_coreutils(&first_bytes) {
+        verbose_print(|| "hashes file is in coreutils format", true);
+        let hash = match algo {
+            Some(hash) => hash,
+            None => {
+                let hash = format::infer_coreutils_hash(hash.len())
+                    .ok_or(Error::UnknownDigest(hash.len()))?;
+                eprintln!(
+                    "WARNING: assuming the hashes file uses {hash}, inferred from the digest length. Other algorithms, such as blake2b from b2sum, have the same length, use -a to select it"
+                );
+                hash
+            }
+        };
+        return Ok((
+            HashesFile::new(lines, Format::Coreutils, vec![]),
+            vec![hash],
+        ));
+    }
+    format::parse_bsd(&first_bytes).ok_or(Error::FileFormat)?;
+    verbose_print(|| "hashes file is in bsd format", true);
+    let mut hashes_file = HashesFile::new(lines, Format::Bsd, vec![]);
+    let (path, values) = hashes_file.next_bsd_group()?.ok_or(Error::FileFormat)?;
+    let (hashes, values): (Vec<HashType>, Vec<String>) = values.into_iter().unzip();
+    hashes_file.hashes = hashes.clone();
+    hashes_file
+        .pending
+        .push_back(HashData::with_hash(path, Some(values.join(","))));
+    Ok((hashes_file, hashes))
+}
+
+/// Optional part of the native header.
+struct Extension {
+    version: u32,
+    attrs: Vec<Attr>,
+    base: Option<PathBuf>,
+    patterns: Patterns,
+    symlinks: Option<Symlinks>,
+    one_file_system: bool,
+    special_files: Option<SpecialFiles>,
+    hash_block_devices: bool,
+}
+
+/// Reads the native header, returning the hash algorithms and the header extension.
+fn load_native_header(lines: &mut HashesLines) -> Result<(Vec<HashType>, Extension), Error> {
+    match lines.next_line()? {
         None => return Err(Error::FileFormat),
-        Some(Err(err)) => return Err(Error::ReadLine(err)),
-        Some(Ok(line)) => match line.rsplit_once(char::is_whitespace) {
+        Some(line) => match line.rsplit_once(char::is_whitespace) {
             Some((VERSION_STR, version)) => {
                 let current = env!("CARGO_PKG_VERSION");
                 if current != version {
@@ -70,10 +631,9 @@ pub fn load_check_file(path: Option<PathBuf>) -> Result<(HashesFile, Vec<HashTyp
             _ => return Err(Error::FileFormat),
         },
     };
-    let hashes = match lines.next() {
+    let hashes = match lines.next_line()? {
         None => return Err(Error::FileFormat),
-        Some(Err(err)) => return Err(Error::ReadLine(err)),
-        Some(Ok(line)) => match line.split_once(char::is_whitespace) {
+        Some(line) => match line.split_once(char::is_whitespace) {
             Some((HASH_ALGO_STR, hashes)) => {
                 let mut hash_list = vec![];
                 for hash in hashes.split(',') {
@@ -84,10 +644,9 @@ pub fn load_check_file(path: Option<PathBuf>) -> Result<(HashesFile, Vec<HashTyp
             _ => return Err(Error::FileFormat),
         },
     };
-    match lines.next() {
+    match lines.next_line()? {
         None => return Err(Error::FileFormat),
-        Some(Err(err)) => return Err(Error::ReadLine(err)),
-        Some(Ok(line)) => {
+        Some(line) => {
             let (start, finish) = line.split_once(" - ").ok_or(Error::FileFormat)?;
             match start.split_once(char::is_whitespace) {
                 Some((TIME_START_STR, NO_DATE_STR)) => (),
@@ -105,63 +664,168 @@ pub fn load_check_file(path: Option<PathBuf>) -> Result<(HashesFile, Vec<HashTyp
             }
         }
     };
-    Ok((lines, hashes))
+    let extension = read_header_extension(lines)?;
+    Ok((hashes, extension))
 }
 
-enum ComparedPath {
-    Audit(AuditError),
-    Unrelated,
+/// Reads the optional `key value` lines that may follow the time line, ending on a blank line.
+/// Entry lines always contain a `|`, so before the `format` line, which comes first since
+/// version 2, any line without one belongs to the extension.
+fn read_header_extension(lines: &mut HashesLines) -> Result<Extension, Error> {
+    let mut extension = Extension {
+        version: 1,
+        attrs: vec![],
+        base: None,
+        patterns: Patterns::default(),
+        symlinks: None,
+        one_file_system: false,
+        special_files: None,
+        hash_block_devices: false,
+    };
+    while let Some(line) = lines.peek_bytes()? {
+        if extension.version == 1 && line.contains(&b'|') {
+            break;
+        }
+        let line = lines.next_line()?.ok_or(Error::FileFormat)?;
+        match line.split_once(char::is_whitespace) {
+            _ if line.is_empty() => break,
+            _ if line == ONE_FILE_SYSTEM_STR => extension.one_file_system = true,
+            _ if line == HASH_BLOCK_DEVICES_STR => extension.hash_block_devices = true,
+            Some((FORMAT_STR, value)) => {
+                let version = value.parse().map_err(|_| Error::FileFormat)?;
+                if version > FORMAT_VERSION {
+                    return Err(Error::Unsupported(format!(
+                        "hashes file format {version}, this program reads up to format {FORMAT_VERSION}"
+                    )));
+                }
+                extension.version = version;
+            }
+            Some((EXCLUDE_STR, pattern)) => extension.patterns.exclude.push(pattern.to_owned()),
+            Some((INCLUDE_STR, pattern)) => extension.patterns.include.push(pattern.to_owned()),
+            Some((IGNORE_FILES_STR, names)) => {
+                extension.patterns.ignore_files = names.split(',').map(str::to_owned).collect();
+            }
+            Some((SPECIAL_FILES_STR, special_files)) => {
+                extension.special_files =
+                    Some(special_files.parse().map_err(|_| Error::FileFormat)?);
+            }
+            Some((SYMLINKS_STR, symlinks)) => {
+                extension.symlinks = Some(symlinks.parse().map_err(|_| Error::FileFormat)?);
+            }
+            Some((BASE_STR, base)) => {
+                extension.base = Some(unescape_path(base).ok_or(Error::FileFormat)?);
+            }
+            Some((ATTRS_STR, attrs)) => {
+                for attr in attrs.split(',') {
+                    extension
+                        .attrs
+                        .push(Attr::from_str(attr).map_err(|_| Error::FileFormat)?);
+                }
+            }
+            Some((NON_CRYPTO_STR, hashes)) => {
+                eprintln!(
+                    "WARNING: the hashes file uses non-cryptographic checksums ({hashes}), they cannot detect deliberate tampering"
+                );
+            }
+            _ => eprintln!("WARNING: ignoring unknown hashes file header line: {line}"),
+        }
+    }
+    Ok(extension)
 }
 
-fn compare_paths(reader_path: &Path, path: &Path) -> Result<(), ComparedPath> {
-    verbose_print(
-        || format!("comparing path {:?} to {:?}", reader_path, path),
-        true,
-    );
-    if reader_path == path {
-        return Ok(());
-    }
-    // This assumes that this program implementation cannot create a hashes file
-    // describing the same directory being empty and filled at the same time, i.e.:
-    // both `/dir|` and `/dir|file.txt` simultaneausly.
-    // If the hashes file has been altered or malformed, the auditing process may return
-    // an incorrect result.
-    match (reader_path.is_dir(), path.is_dir()) {
-        (true, false) => match path.ancestors().nth(1) {
-            Some(ancestor) if ancestor == reader_path => {
-                Err(ComparedPath::Audit(AuditError::Extra(path_string(path))))
-            }
-            _ => Err(ComparedPath::Unrelated),
-        },
-        (false, true) => match reader_path.ancestors().nth(1) {
-            Some(ancestor) if ancestor == path => {
-                Err(ComparedPath::Audit(AuditError::EmptyDir(path_string(path))))
-            }
-            _ => Err(ComparedPath::Unrelated),
-        },
-        _ => Err(ComparedPath::Unrelated),
+/// Size, modification time and hash recorded for a file.
+struct Recorded {
+    size: u64,
+    modified: Option<Timestamp>,
+    hash: String,
+}
+
+/// Sizes and modification times recorded in a hashes file, checked by the hashing threads
+/// before hashing each file.
+pub struct Prefilter {
+    entries: HashMap<PathBuf, Recorded>,
+    /// Trust the recorded hash of files whose size and modification time are unchanged.
+    quick: bool,
+}
+
+impl Prefilter {
+    fn precheck(&self, hash_data: &HashData) -> Precheck {
+        let Some(recorded) = self.entries.get(hash_data.path()) else {
+            return Precheck::Hash;
+        };
+        if hash_data.size != Some(recorded.size) {
+            return Precheck::Differs;
+        }
+        let modified = hash_data.times.and_then(|times| times.modified);
+        if self.quick && recorded.modified.is_some() && recorded.modified == modified {
+            return Precheck::Unchanged(recorded.hash.clone());
+        }
+        Precheck::Hash
     }
 }
 
-enum ReaderErr {
-    Error(Error),
-    Audit(AuditError),
+/// Hashing threads side of an audit, sending every entry to the [`Checker`].
+pub struct AuditSource<'a> {
+    sender: Sender<HashData>,
+    prefilter: Option<&'a Prefilter>,
+}
+
+impl<'a> AuditSource<'a> {
+    pub fn new(sender: Sender<HashData>, prefilter: Option<&'a Prefilter>) -> Self {
+        Self { sender, prefilter }
+    }
 }
 
-impl HashHandler for Sender<HashData> {
+impl HashHandler for AuditSource<'_> {
     fn handle(&self, hash_data: HashData) -> Result<(), Error> {
-        self.send(hash_data).unwrap();
+        self.sender.send(hash_data).unwrap();
         Ok(())
     }
+
+    fn precheck(&self, hash_data: &HashData) -> Precheck {
+        match self.prefilter {
+            Some(prefilter) => prefilter.precheck(hash_data),
+            None => Precheck::Hash,
+        }
+    }
+}
+
+/// Hashes file entry stored in the [`Checker`] index, hashed and compared by path only.
+struct Indexed(HashData);
+
+impl Borrow<Path> for Indexed {
+    fn borrow(&self) -> &Path {
+        self.0.path()
+    }
 }
 
+impl Hash for Indexed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.path().hash(state);
+    }
+}
+
+impl PartialEq for Indexed {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.path() == other.0.path()
+    }
+}
+
+impl Eq for Indexed {}
+
 pub struct Checker {
     source: Receiver<HashData>,
-    reader: Lines<BufReader<File>>,
-    backlog: VecDeque<HashData>,
-    audit_err: bool,
+    reader: HashesFile,
+    /// Entries read from the hashes file that were not matched yet.
+    index: HashSet<Indexed>,
+    /// Parent directories of every entry read from the hashes file.
+    parents: HashSet<PathBuf>,
+    /// Audited files of every recorded hardlink set, with their current hardlink set.
+    links: HashMap<LinkId, Vec<(PathBuf, Option<LinkId>)>>,
+    counts: AuditCounts,
     early: bool,
     empty_dirs: bool,
+    json: bool,
 }
 
 impl Checker {
@@ -170,102 +834,69 @@ impl Checker {
         source: Receiver<HashData>,
         early: bool,
         empty_dirs: bool,
+        json: bool,
     ) -> Self {
         Self {
             source,
             reader,
-            backlog: VecDeque::with_capacity(100),
-            audit_err: false,
+            index: HashSet::new(),
+            parents: HashSet::new(),
+            links: HashMap::new(),
+            counts: AuditCounts::default(),
             early,
             empty_dirs,
+            json,
         }
     }
 
     fn read_next(&mut self) -> Result<Option<HashData>, Error> {
-        let next_line = match self.reader.next() {
-            Some(result) => result.map_err(Error::ReadLine)?,
-            None => return Ok(None),
+        let Some(hash_data) = self.reader.next_entry(self.empty_dirs)? else {
+            return Ok(None);
         };
-        verbose_print(
-            || format!("reading next line from hashes file: {:?}", &next_line),
-            true,
-        );
-        HashData::try_from_string(next_line, self.empty_dirs).map(Some)
+        if let Some(parent) = hash_data.path().parent()
+            && !self.parents.contains(parent)
+        {
+            self.parents.insert(parent.to_path_buf());
+        }
+        Ok(Some(hash_data))
     }
 
-    fn search_backlog(&mut self, hd @ HashData(path, hash): &HashData) -> Result<bool, AuditError> {
-        verbose_print(|| format!("searching {hd} in backlog"), true);
-        let len = self.backlog.len();
-        for _ in 0..len {
-            if is_canceled() {
-                return Ok(false);
-            }
-            if let Some(HashData(list_path, list_hash)) = self.backlog.pop_front() {
-                match compare_paths(&list_path, path) {
-                    Ok(_) => {
-                        verbose_print(|| format!("found {hd} in backlog"), true);
-                        return if &list_hash == hash {
-                            Ok(true)
-                        } else {
-                            Err(AuditError::Mismatch(path_string(path)))
-                        };
-                    }
-                    Err(ComparedPath::Unrelated) => {
-                        verbose_print(|| format!("pushing {hd} to backlog"), true);
-                        self.backlog.push_back(HashData(list_path, list_hash));
-                        continue;
-                    }
-                    Err(ComparedPath::Audit(audit_err)) => {
-                        if let AuditError::EmptyDir(_) = &audit_err {
-                            self.backlog.push_back(HashData(list_path, list_hash));
-                        }
-                        return Err(audit_err);
-                    }
-                }
+    /// Removes the entry for `path` from the index, reading the hashes file only as far as
+    /// needed to find it. Every unrelated line read on the way is indexed for later lookups.
+    fn take_entry(&mut self, path: &Path) -> Result<Option<HashData>, Error> {
+        if let Some(Indexed(hash_data)) = self.index.take(path) {
+            verbose_print(|| format!("found {path:?} in index"), true);
+            return Ok(Some(hash_data));
+        }
+        verbose_print(|| format!("searching {path:?} on hashes file"), true);
+        while let Some(hash_data) = self.read_next()? {
+            if hash_data.path() == path {
+                verbose_print(|| format!("found {path:?} in hashes file"), true);
+                return Ok(Some(hash_data));
             }
+            self.index.insert(Indexed(hash_data));
         }
-        Ok(false)
+        Ok(None)
     }
 
-    fn search_reader(
-        &mut self,
-        hd @ HashData(path, hash): &HashData,
-    ) -> Option<Result<(), ReaderErr>> {
-        verbose_print(|| format!("searching {hd} on hashes file"), true);
-        loop {
-            if is_canceled() {
-                return Some(Ok(()));
-            }
-            break match self.read_next() {
-                Err(err) => Some(Err(ReaderErr::Error(err))),
-                Ok(None) => None,
-                Ok(Some(HashData(reader_path, reader_hash))) => {
-                    match compare_paths(&reader_path, path) {
-                        Ok(_) => {
-                            verbose_print(|| format!("found {hd} in hashes file"), true);
-                            if &reader_hash == hash {
-                                Some(Ok(()))
-                            } else {
-                                Some(Err(ReaderErr::Audit(AuditError::Mismatch(path_string(
-                                    path,
-                                )))))
-                            }
-                        }
-                        Err(ComparedPath::Unrelated) => {
-                            verbose_print(|| format!("pushing {hd} to backlog"), true);
-                            self.backlog.push_back(HashData(reader_path, reader_hash));
-                            continue;
-                        }
-                        Err(ComparedPath::Audit(audit_err)) => {
-                            if let AuditError::EmptyDir(_) = &audit_err {
-                                self.backlog.push_back(HashData(reader_path, reader_hash));
-                            }
-                            Some(Err(ReaderErr::Audit(audit_err)))
-                        }
-                    }
-                }
-            };
+    /// Classifies a path found in the audit source that is not described by the hashes file.
+    fn unlisted(&mut self, hash_data: &HashData) -> AuditError {
+        let path = hash_data.path();
+        // This assumes that this program implementation cannot create a hashes file
+        // describing the same directory being empty and filled at the same time, i.e.:
+        // both `/dir|` and `/dir/file.txt|hash` simultaneausly.
+        // If the hashes file has been altered or malformed, the auditing process may return
+        // an incorrect result.
+        if hash_data.hash().is_none() && self.parents.contains(path) {
+            return AuditError::EmptyDir(path_string(path));
         }
+        if let Some(parent) = path.parent()
+            && let Some(Indexed(parent_data)) = self.index.get(parent)
+            && parent_data.hash().is_none()
+        {
+            self.index.remove(parent);
+        }
+        AuditError::Extra(path_string(path))
     }
 
     fn search(&mut self) -> Result<(), Error> {
@@ -274,54 +905,260 @@ impl Checker {
             if is_canceled() {
                 return Ok(());
             }
-            if !self.backlog.is_empty() {
-                match self.search_backlog(&hash_data) {
-                    Ok(true) => continue,
-                    Ok(false) => (),
-                    Err(err) => {
-                        self.audit_err = true;
-                        err.print_and_cancel(self.early)
-                    }
-                };
+            let entry = cancel_on_err(self.take_entry(hash_data.path()))?;
+            if let Some(link) = entry.as_ref().and_then(attrs::link) {
+                let current = attrs::link(&hash_data);
+                let files = self.links.entry(link).or_default();
+                files.push((hash_data.path.clone(), current));
             }
-            match self.search_reader(&hash_data) {
-                Some(Ok(_)) => continue,
-                Some(Err(err)) => match err {
-                    ReaderErr::Error(err) => cancel_on_err(Err(err))?,
-                    ReaderErr::Audit(err) => {
-                        self.audit_err = true;
-                        err.print_and_cancel(self.early);
+            let audit_err = match entry {
+                Some(entry) if entry.matches(&hash_data) => {
+                    let drift = attrs::drift(&entry, &hash_data);
+                    if drift.is_empty() {
+                        continue;
                     }
-                },
-                None => {
-                    self.audit_err = true;
-                    AuditError::Extra(path_string(&hash_data.0)).print_and_cancel(self.early);
+                    AuditError::Drift(path_string(hash_data.path()), drift)
                 }
+                Some(_) => AuditError::Mismatch(path_string(hash_data.path())),
+                None => self.unlisted(&hash_data),
             };
+            self.report(audit_err);
         }
         self.flush_reader()
     }
 
+    /// Reads the whole hashes file into the index, instead of only as far as needed, and
+    /// collects the recorded sizes for [`Prefilter`]. `quick` also trusts unchanged files.
+    /// Fails if the hashes file records no sizes, or no modification times for `quick`,
+    /// since every file would be hashed anyway.
+    pub fn prefilter(&mut self, quick: bool) -> Result<Prefilter, Error> {
+        verbose_print(|| "loading the whole hashes file", true);
+        self.flush_reader()?;
+        let mut files = self
+            .index
+            .iter()
+            .map(|Indexed(hash_data)| hash_data)
+            .filter(|hash_data| hash_data.hash.is_some())
+            .peekable();
+        if files.peek().is_some() {
+            let (mut sizes, mut modified) = (false, false);
+            for hash_data in files {
+                sizes |= hash_data.size.is_some();
+                modified |= hash_data
+                    .times
+                    .is_some_and(|times| times.modified.is_some());
+            }
+            if !sizes {
+                return Err(Error::Unsupported(
+                    "the hashes file records no sizes, --size-first and --quick need the native size attribute, hashdeep, jsonl or csv with the size column".to_owned(),
+                ));
+            }
+            if quick && !modified {
+                return Err(Error::Unsupported(
+                    "the hashes file records no modification times, --quick needs the native mtime attribute or csv with the mtime column".to_owned(),
+                ));
+            }
+        }
+        let entries = self
+            .index
+            .iter()
+            .filter_map(|Indexed(hash_data)| {
+                let recorded = Recorded {
+                    size: hash_data.size?,
+                    modified: hash_data.times.and_then(|times| times.modified),
+                    hash: hash_data.hash.clone()?,
+                };
+                Some((hash_data.path.clone(), recorded))
+            })
+            .collect();
+        Ok(Prefilter { entries, quick })
+    }
+
     fn flush_reader(&mut self) -> Result<(), Error> {
         while let Some(hash_data) = self.read_next()? {
-            self.backlog.push_back(hash_data);
+            self.index.insert(Indexed(hash_data));
         }
         Ok(())
     }
 
-    pub fn check(&mut self) -> Result<bool, Error> {
+    /// Reports the audited files of every recorded hardlink set that are no longer hardlinks
+    /// of the first one, by path.
+    fn check_links(&mut self) {
+        let mut unlinked = vec![];
+        for (_, mut files) in self.links.drain() {
+            files.sort_unstable_by(|(path, _), (other, _)| path.cmp(other));
+            let (first, first_link) = &files[0];
+            for (path, link) in &files[1..] {
+                if link.is_none() || link != first_link {
+                    unlinked.push(AuditError::Unlinked(path_string(path), path_string(first)));
+                }
+            }
+        }
+        unlinked.sort_unstable_by(|audit_err, other| audit_err.path().cmp(other.path()));
+        for audit_err in unlinked {
+            if is_canceled() {
+                break;
+            }
+            self.report(audit_err);
+        }
+    }
+
+    pub fn check(&mut self) -> Result<AuditOutcome, Error> {
         self.search()?;
-        if self.backlog.is_empty() {
-            verbose_print(|| "search done, backlog is empty", true);
-            return Ok(self.audit_err);
+        self.check_links();
+        if self.index.is_empty() {
+            verbose_print(|| "search done, index is empty", true);
+            return Ok(self.outcome());
         }
-        verbose_print(|| "search done, backlog not empty", true);
-        for HashData(path, _) in self.backlog.drain(..) {
+        verbose_print(|| "search done, index not empty", true);
+        let mut not_found: Vec<PathBuf> = self
+            .index
+            .drain()
+            .map(|Indexed(hash_data)| hash_data.path)
+            .collect();
+        not_found.sort_unstable();
+        for path in not_found {
             if is_canceled() {
-                return Ok(self.audit_err);
+                break;
             }
-            AuditError::NotFound(path_string(&path)).print_and_cancel(self.early)
+            self.report(AuditError::NotFound(path_string(&path)));
         }
-        Ok(true)
+        Ok(self.outcome())
+    }
+
+    fn report(&mut self, audit_err: AuditError) {
+        self.counts.count(&audit_err);
+        audit_err.print_and_cancel(self.early, self.json);
+    }
+
+    fn outcome(&self) -> AuditOutcome {
+        match self.counts.total() {
+            0 => AuditOutcome::Clean,
+            _ => AuditOutcome::Discrepancies(self.counts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::mpsc};
+
+    use super::*;
+
+    /// Native hashes file with the given entry lines.
+    fn native(entries: &[&str]) -> String {
+        let version = env!("CARGO_PKG_VERSION");
+        format!(
+            "{VERSION_STR} {version}\n{HASH_ALGO_STR} sha256\n\
+            {TIME_START_STR} {NO_DATE_STR} - {TIME_FINISH_STR} {NO_DATE_STR}\n\
+            {FORMAT_STR} {FORMAT_VERSION}\n\n{}\n",
+            entries.join("\n")
+        )
+    }
+
+    fn checker(hashes_file: &str) -> (Checker, Sender<HashData>) {
+        crate::VERBOSE.get_or_init(|| false);
+        let reader = Box::new(Cursor::new(hashes_file.as_bytes().to_vec()));
+        let (reader, _) = read_check_file(reader, None).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        (Checker::new(reader, receiver, false, true, false), sender)
+    }
+
+    /// Audits the paths sent in order, `None` hashes standing for empty directories, and
+    /// returns the not found, mismatch, extra and empty directory counts.
+    fn audit(hashes_file: &str, sent: &[(&str, Option<&str>)]) -> [usize; 4] {
+        let (mut checker, sender) = checker(hashes_file);
+        for (path, hash) in sent {
+            let hash_data = HashData::with_hash(PathBuf::from(path), hash.map(str::to_owned));
+            sender.send(hash_data).unwrap();
+        }
+        drop(sender);
+        let counts = match checker.check().unwrap() {
+            AuditOutcome::Clean => AuditCounts::default(),
+            AuditOutcome::Discrepancies(counts) => counts,
+        };
+        [
+            counts.not_found,
+            counts.mismatch,
+            counts.extra,
+            counts.empty_dir,
+        ]
+    }
+
+    #[test]
+    fn csv_records_round_trip() {
+        crate::VERBOSE.get_or_init(|| false);
+        let hashes = vec![HashType::Md5, HashType::Sha1];
+        let format = Format::from_str("csv:hashes,path,size").unwrap();
+        let paths = ["plain", "dir/a \"b\", c\nd", "cr\r\nlf", "trailing\r"];
+        let mut hashes_file = format.header(&hashes, &jiff::Zoned::now()).unwrap();
+        for path in paths {
+            let mut hash_data = HashData::with_hash(PathBuf::from(path), Some("ab,cd".to_owned()));
+            hash_data.size = Some(42);
+            hashes_file.push_str(&format.entry(&hashes, &[], &hash_data));
+        }
+        let reader = Box::new(Cursor::new(hashes_file.into_bytes()));
+        let (mut reader, parsed_hashes) = read_check_file(reader, None).unwrap();
+        assert_eq!(parsed_hashes, hashes);
+        for path in paths {
+            let hash_data = reader.next_entry(false).unwrap().unwrap();
+            assert_eq!(hash_data.path(), Path::new(path));
+            assert_eq!(hash_data.hash(), Some("ab,cd"));
+            assert_eq!(hash_data.size, Some(42));
+        }
+        assert!(reader.next_entry(false).unwrap().is_none());
+    }
+
+    #[test]
+    fn prefilter_needs_recorded_sizes() {
+        let (mut without_sizes, _) = checker(&native(&["a|aa"]));
+        assert!(without_sizes.prefilter(false).is_err());
+        let with_sizes = native(&["a|aa|3"]).replace(
+            &format!("{FORMAT_STR} {FORMAT_VERSION}\n"),
+            &format!("{FORMAT_STR} {FORMAT_VERSION}\n{ATTRS_STR} size\n"),
+        );
+        assert!(checker(&with_sizes).0.prefilter(false).is_ok());
+        assert!(checker(&with_sizes).0.prefilter(true).is_err());
+    }
+
+    #[test]
+    fn out_of_order_entries_are_matched() {
+        let hashes_file = native(&["a|aa", "b|bb", "c/d|cc"]);
+        let sent = [("c/d", Some("cc")), ("a", Some("aa")), ("b", Some("bb"))];
+        assert_eq!(audit(&hashes_file, &sent), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn changed_files_do_not_match() {
+        let hashes_file = native(&["a|aa", "b|bb"]);
+        let sent = [("b", Some("ff")), ("a", Some("aa"))];
+        assert_eq!(audit(&hashes_file, &sent), [0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn unsent_entries_are_not_found() {
+        let hashes_file = native(&["a|aa", "b|bb", "c|cc"]);
+        assert_eq!(audit(&hashes_file, &[("b", Some("bb"))]), [2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn unlisted_files_are_extra() {
+        let hashes_file = native(&["a|aa"]);
+        let sent = [("new", Some("ff")), ("a", Some("aa"))];
+        assert_eq!(audit(&hashes_file, &sent), [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn files_in_recorded_empty_dirs_are_extra() {
+        let hashes_file = native(&["a|aa", "dir|"]);
+        let sent = [("dir/new", Some("ff")), ("a", Some("aa"))];
+        assert_eq!(audit(&hashes_file, &sent), [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn emptied_dirs_are_reported() {
+        let hashes_file = native(&["a|aa", "dir/f|ff"]);
+        let sent = [("dir", None), ("a", Some("aa"))];
+        assert_eq!(audit(&hashes_file, &sent), [1, 0, 0, 1]);
     }
 }
@@ -0,0 +1,158 @@
+use std::{path::Path, sync::Arc};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+
+use crate::Error;
+
+/// Ignore file read in every traversed directory unless the hashes file says otherwise.
+pub const GOBLINIGNORE: &str = ".goblinignore";
+const GITIGNORE: &str = ".gitignore";
+
+/// Rules selecting the paths found while traversing directories.
+#[derive(Clone, Default)]
+pub struct Patterns {
+    pub exclude: Vec<String>,
+    /// When not empty, only files matching one of these are kept.
+    pub include: Vec<String>,
+    /// Names of the ignore files read in every traversed directory, with gitignore syntax.
+    pub ignore_files: Vec<String>,
+}
+
+impl Patterns {
+    /// Whether there are exclude or include glob patterns.
+    pub fn has_globs(&self) -> bool {
+        !self.exclude.is_empty() || !self.include.is_empty()
+    }
+
+    /// Defaults the ignore files to `.goblinignore`, adding `.gitignore` if `gitignore`.
+    pub fn with_ignore_files(mut self, gitignore: bool) -> Self {
+        if self.ignore_files.is_empty() {
+            self.ignore_files.push(GOBLINIGNORE.to_owned());
+        }
+        if gitignore && !self.ignore_files.iter().any(|name| name == GITIGNORE) {
+            self.ignore_files.push(GITIGNORE.to_owned());
+        }
+        self
+    }
+}
+
+/// Rules of the ignore files found in a directory, chained to those of its ancestors.
+pub struct Ignores {
+    matcher: Gitignore,
+    parent: Option<Arc<Ignores>>,
+}
+
+impl Ignores {
+    /// Whether the deepest ignore file with a rule for `path` ignores it, negated rules
+    /// keep it.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignores = Some(self);
+        while let Some(current) = ignores {
+            match current.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => ignores = current.parent.as_deref(),
+            }
+        }
+        false
+    }
+}
+
+/// Compiled [`Patterns`]. Patterns without a `/` match the file name, the rest match the
+/// whole path as recorded in hashes files, with `*` not crossing directories.
+pub struct Filter {
+    patterns: Patterns,
+    exclude: Globs,
+    include: Globs,
+}
+
+struct Globs {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Globs {
+    fn new(patterns: &[String]) -> Result<Self, Error> {
+        let invalid = |err: globset::Error| Error::Glob(err.to_string());
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            if pattern.contains('/') {
+                let glob = GlobBuilder::new(pattern).literal_separator(true).build();
+                paths.add(glob.map_err(invalid)?);
+            } else {
+                names.add(Glob::new(pattern).map_err(invalid)?);
+            }
+        }
+        Ok(Self {
+            names: names.build().map_err(invalid)?,
+            paths: paths.build().map_err(invalid)?,
+        })
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|name| self.names.is_match(name))
+            || self.paths.is_match(path)
+    }
+}
+
+impl Filter {
+    pub fn new(patterns: Patterns) -> Result<Self, Error> {
+        Ok(Self {
+            exclude: Globs::new(&patterns.exclude)?,
+            include: Globs::new(&patterns.include)?,
+            patterns,
+        })
+    }
+
+    pub fn patterns(&self) -> &Patterns {
+        &self.patterns
+    }
+
+    /// Reads the ignore files of `dir`, returning the rules that apply to its entries.
+    pub fn load_ignores(
+        &self,
+        dir: &Path,
+        parent: Option<&Arc<Ignores>>,
+    ) -> Result<Option<Arc<Ignores>>, Error> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for name in &self.patterns.ignore_files {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            found = true;
+            if let Some(err) = builder.add(&path) {
+                return Err(Error::Glob(format!("{}: {err}", path.display())));
+            }
+        }
+        if !found {
+            return Ok(parent.cloned());
+        }
+        let matcher = builder
+            .build()
+            .map_err(|err| Error::Glob(format!("{}: {err}", dir.display())))?;
+        Ok(Some(Arc::new(Ignores {
+            matcher,
+            parent: parent.cloned(),
+        })))
+    }
+
+    /// Whether a path found inside a traversed directory is kept. `path` is relative to the
+    /// base directory, if there is one. Directories are only subject to the exclusions.
+    pub fn is_kept(&self, path: &Path, is_dir: bool) -> bool {
+        if !self.patterns.has_globs() {
+            return true;
+        }
+        if self.exclude.is_match(path) {
+            return false;
+        }
+        is_dir || self.patterns.include.is_empty() || self.include.is_match(path)
+    }
+}
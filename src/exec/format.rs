@@ -0,0 +1,524 @@
+mod csv;
+mod dfxml;
+mod jsonl;
+
+use std::{
+    env,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use jiff::Zoned;
+
+use crate::{Error, HashType};
+
+use super::{
+    HashData, SpecialFiles, Symlinks,
+    attrs::{self, Attr},
+    path_from_bytes, path_string,
+};
+
+/// Layout of a hashes file.
+#[derive(Clone, Default)]
+pub enum Format {
+    /// This program's own format: a header followed by `path|hash,...` lines.
+    #[default]
+    Native,
+    /// GNU coreutils `*sum` format: `<hex>  <path>`, or `<hex> *<path>` for binary mode.
+    Coreutils,
+    /// BSD tag format, also written by coreutils with `--tag`: `SHA256 (<path>) = <hex>`.
+    Bsd,
+    /// hashdeep audit format: a `%%%% HASHDEEP-1.0` header and `size,<hex>,...,<path>` lines.
+    Hashdeep,
+    /// Digital Forensics XML, a `<fileobject>` element per entry. It cannot be audited.
+    Dfxml,
+    /// JSON Lines, an object with the path, type, size and digests per entry.
+    Jsonl,
+    /// RFC 4180 CSV with a header record naming the selected columns.
+    Csv(Vec<csv::Column>),
+}
+
+/// Digests of a single entry paired with their algorithm.
+pub type HashValues = Vec<(HashType, String)>;
+
+pub use csv::{
+    Column as CsvColumn, is_record_open as is_csv_record_open, parse as parse_csv,
+    parse_header as parse_csv_header,
+};
+pub use jsonl::parse as parse_jsonl;
+
+/// Start of every XML document, DFXML included.
+pub const XML_STR: &str = "<?xml";
+/// Start of every JSON Lines entry.
+pub const JSONL_STR: &str = "{";
+pub const HASHDEEP_STR: &str = "%%%% HASHDEEP-1.0";
+const HASHDEEP_COLUMNS_STR: &str = "%%%% ";
+const HASHDEEP_SIZE_STR: &str = "size";
+const HASHDEEP_FILENAME_STR: &str = "filename";
+/// Prefix of the hashdeep comment lines.
+pub const HASHDEEP_COMMENT_STR: &str = "##";
+
+impl Format {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Format::Native => "native",
+            Format::Coreutils => "coreutils",
+            Format::Bsd => "bsd",
+            Format::Hashdeep => "hashdeep",
+            Format::Dfxml => "dfxml",
+            Format::Jsonl => "jsonl",
+            Format::Csv(_) => "csv",
+        }
+    }
+
+    /// Fails if the format is unable to describe the requested output.
+    pub fn validate(
+        &self,
+        hashes: &[HashType],
+        attrs: &[Attr],
+        empty_dirs: bool,
+        symlinks: Symlinks,
+        special_files: SpecialFiles,
+    ) -> Result<(), Error> {
+        match self {
+            Format::Native => Ok(()),
+            _ if symlinks == Symlinks::Record => Err(Error::Unsupported(format!(
+                "the {self} format cannot record symlinks, use the native format"
+            ))),
+            _ if special_files == SpecialFiles::Record => Err(Error::Unsupported(format!(
+                "the {self} format cannot record special files, use the native format"
+            ))),
+            _ if !attrs.is_empty() => Err(Error::Unsupported(format!(
+                "the {self} format cannot record attributes, use the native format"
+            ))),
+            Format::Dfxml | Format::Jsonl => Ok(()),
+            Format::Csv(columns) => csv::validate(columns, hashes),
+            Format::Coreutils if hashes.len() > 1 => Err(Error::Unsupported(format!(
+                "the {self} format holds a single hash algorithm per file"
+            ))),
+            Format::Hashdeep if !hashes.iter().all(is_hashdeep_hash) => Err(Error::Unsupported(
+                format!("the {self} format only supports md5, sha1, sha256, tiger and whirlpool"),
+            )),
+            Format::Coreutils | Format::Bsd | Format::Hashdeep if empty_dirs => Err(
+                Error::Unsupported(format!("the {self} format cannot record empty directories")),
+            ),
+            Format::Coreutils | Format::Bsd | Format::Hashdeep => Ok(()),
+        }
+    }
+
+    /// Formats the lines written before any entry, if the format has a header.
+    pub fn header(&self, hashes: &[HashType], start: &Zoned) -> Option<String> {
+        match self {
+            Format::Dfxml => Some(dfxml::header(start)),
+            Format::Csv(columns) => Some(csv::header(columns, hashes)),
+            Format::Hashdeep => {
+                let columns: Vec<String> = hashes.iter().map(HashType::to_string).collect();
+                let cwd = env::current_dir()
+                    .map(|cwd| path_string(&cwd))
+                    .unwrap_or_default();
+                let invocation: Vec<String> = env::args().collect();
+                Some(format!(
+                    "{HASHDEEP_STR}\n{HASHDEEP_COLUMNS_STR}{HASHDEEP_SIZE_STR},{},{HASHDEEP_FILENAME_STR}\n\
+                    {HASHDEEP_COMMENT_STR} Invoked from: {cwd}\n\
+                    {HASHDEEP_COMMENT_STR} $ {}\n{HASHDEEP_COMMENT_STR}\n",
+                    columns.join(","),
+                    invocation.join(" ")
+                ))
+            }
+            Format::Native | Format::Coreutils | Format::Bsd | Format::Jsonl => None,
+        }
+    }
+
+    /// Formats the lines written after all entries, if the format has a footer.
+    pub fn footer(&self) -> Option<String> {
+        match self {
+            Format::Dfxml => Some(dfxml::footer()),
+            Format::Native
+            | Format::Coreutils
+            | Format::Bsd
+            | Format::Hashdeep
+            | Format::Jsonl
+            | Format::Csv(_) => None,
+        }
+    }
+
+    /// Why the format cannot record `path`, if it cannot. Only the native format records
+    /// every path, the others hold UTF-8 and a lossy form of the name would record a path
+    /// that does not exist.
+    pub fn unrepresentable(&self, path: &Path) -> Option<&'static str> {
+        match self {
+            Format::Native => None,
+            _ if path.to_str().is_none() => Some("its name is not valid UTF-8"),
+            Format::Hashdeep if path_string(path).contains(['\n', '\r']) => {
+                Some("its name contains a line break")
+            }
+            _ => None,
+        }
+    }
+
+    /// Formats the line, or lines, describing `hash_data`, each terminated by a newline.
+    /// Only the native format records `attrs`, after the hashes. Paths the format cannot
+    /// record, see [`Format::unrepresentable`], must be skipped beforehand.
+    pub fn entry(&self, hashes: &[HashType], attrs: &[Attr], hash_data: &HashData) -> String {
+        let path = path_string(hash_data.path());
+        match self {
+            Format::Native if attrs.is_empty() => format!("{hash_data}\n"),
+            Format::Native => format!("{hash_data}|{}\n", attrs::format(attrs, hash_data)),
+            Format::Coreutils => {
+                let (prefix, path) = escape_coreutils(&path);
+                format!("{prefix}{}  {path}\n", hash_data.hash().unwrap_or_default())
+            }
+            Format::Bsd => {
+                let (prefix, path) = escape_coreutils(&path);
+                let values = hash_data.hash().unwrap_or_default().split(',');
+                hashes
+                    .iter()
+                    .zip(values)
+                    .map(|(hash, value)| format!("{prefix}{} ({path}) = {value}\n", bsd_tag(hash)))
+                    .collect()
+            }
+            Format::Hashdeep => format!(
+                "{},{},{path}\n",
+                hash_data.size.unwrap_or_default(),
+                hash_data.hash().unwrap_or_default(),
+            ),
+            Format::Dfxml => dfxml::entry(hashes, hash_data, &path),
+            Format::Jsonl => jsonl::entry(hashes, hash_data, &path),
+            Format::Csv(columns) => csv::entry(columns, hashes, hash_data, &path),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    /// Accepts `csv:<column>,...` to select the CSV columns.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if let Some(columns) = s.strip_prefix("csv") {
+            return match columns.strip_prefix(':') {
+                Some(columns) => csv::parse_columns(columns).map(Format::Csv),
+                None if columns.is_empty() => csv::parse_columns("").map(Format::Csv),
+                None => Err(format!("invalid format: {s}")),
+            };
+        }
+        match s.as_str() {
+            "native" => Ok(Format::Native),
+            "coreutils" => Ok(Format::Coreutils),
+            "bsd" => Ok(Format::Bsd),
+            "hashdeep" => Ok(Format::Hashdeep),
+            "dfxml" => Ok(Format::Dfxml),
+            "jsonl" => Ok(Format::Jsonl),
+            _ => Err(format!(
+                "invalid format: {s}, possible options are: native, coreutils, bsd, hashdeep, dfxml, jsonl, csv"
+            )),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Parses a single coreutils line into its digest and path. The path is taken as raw bytes,
+/// as written by the coreutils tools. A line starting with a backslash has its path escaped,
+/// with `\\`, `\n` and `\r` standing for the literal characters.
+pub fn parse_coreutils(line: &[u8]) -> Option<(String, PathBuf)> {
+    let (escaped, line) = match line.strip_prefix(b"\\") {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (hash, path) = split_bytes(line, b" ")?;
+    let path = path
+        .strip_prefix(b" ")
+        .or_else(|| path.strip_prefix(b"*"))?;
+    if path.is_empty() {
+        return None;
+    }
+    Some((parse_digest(hash)?, coreutils_path(path, escaped)?))
+}
+
+/// Parses a single BSD tag line into its algorithm, path and digest.
+pub fn parse_bsd(line: &[u8]) -> Option<(HashType, PathBuf, String)> {
+    let (escaped, line) = match line.strip_prefix(b"\\") {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (tag, line) = split_bytes(line, b" (")?;
+    let (path, hash) = rsplit_bytes(line, b") = ")?;
+    if path.is_empty() {
+        return None;
+    }
+    let tag = std::str::from_utf8(tag).ok()?;
+    let hash_type = match tag.strip_prefix("BLAKE2b") {
+        Some("") => HashType::from_str("blake2b"),
+        Some(bits) => HashType::from_str(&format!("blake2b:{}", bits.strip_prefix('-')?)),
+        None => HashType::from_str(tag),
+    };
+    Some((
+        hash_type.ok()?,
+        coreutils_path(path, escaped)?,
+        parse_digest(hash)?,
+    ))
+}
+
+/// Lowercase form of a hex digest.
+fn parse_digest(hash: &[u8]) -> Option<String> {
+    if hash.is_empty() || !hash.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(hash).to_ascii_lowercase())
+}
+
+fn coreutils_path(path: &[u8], escaped: bool) -> Option<PathBuf> {
+    match escaped {
+        true => path_from_bytes(unescape_coreutils(path)?),
+        false => path_from_bytes(path.to_vec()),
+    }
+}
+
+/// Splits `bytes` around the first occurrence of `separator`.
+fn split_bytes<'a>(bytes: &'a [u8], separator: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let i = bytes
+        .windows(separator.len())
+        .position(|window| window == separator)?;
+    Some((&bytes[..i], &bytes[i + separator.len()..]))
+}
+
+/// Splits `bytes` around the last occurrence of `separator`.
+fn rsplit_bytes<'a>(bytes: &'a [u8], separator: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let i = bytes
+        .windows(separator.len())
+        .rposition(|window| window == separator)?;
+    Some((&bytes[..i], &bytes[i + separator.len()..]))
+}
+
+fn is_hashdeep_hash(hash: &HashType) -> bool {
+    matches!(
+        hash,
+        HashType::Md5 | HashType::Sha1 | HashType::Sha256 | HashType::Tiger | HashType::Whirlpool
+    )
+}
+
+/// Parses the hashdeep line listing the file columns, returning the hash columns in order.
+pub fn parse_hashdeep_columns(line: &str) -> Option<Vec<HashType>> {
+    let columns = line.strip_prefix(HASHDEEP_COLUMNS_STR)?;
+    let columns = columns.strip_prefix(HASHDEEP_SIZE_STR)?.strip_prefix(',')?;
+    let columns = columns
+        .strip_suffix(HASHDEEP_FILENAME_STR)?
+        .strip_suffix(',')?;
+    let hashes: Vec<HashType> = columns
+        .split(',')
+        .map(HashType::from_str)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    hashes.iter().all(is_hashdeep_hash).then_some(hashes)
+}
+
+/// Parses a hashdeep file line holding the size, `hash_count` digests and the path.
+/// The path is the last column, so it may itself contain commas.
+pub fn parse_hashdeep(line: &str, hash_count: usize) -> Option<HashData> {
+    let mut columns = line.splitn(hash_count + 2, ',');
+    let size = columns.next()?.parse::<u64>().ok()?;
+    let mut hashes = Vec::with_capacity(hash_count);
+    for _ in 0..hash_count {
+        let hash = columns.next()?;
+        if hash.is_empty() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        hashes.push(hash.to_ascii_lowercase());
+    }
+    let path = columns.next().filter(|path| !path.is_empty())?;
+    let mut hash_data = HashData::with_hash(PathBuf::from(path), Some(hashes.join(",")));
+    hash_data.size = Some(size);
+    Some(hash_data)
+}
+
+/// Tag naming `hash` in the BSD format, following the names used by coreutils where they exist.
+fn bsd_tag(hash: &HashType) -> String {
+    match hash {
+        HashType::Blake2b(512) => "BLAKE2b".to_owned(),
+        HashType::Blake2b(bits) => format!("BLAKE2b-{bits}"),
+        _ => hash.to_string().to_uppercase(),
+    }
+}
+
+/// Escapes a path the way coreutils does, returning the backslash line prefix when needed.
+fn escape_coreutils(path: &str) -> (&'static str, String) {
+    if !path.contains(['\\', '\n', '\r']) {
+        return ("", path.to_owned());
+    }
+    let escaped = path
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+    ("\\", escaped)
+}
+
+fn unescape_coreutils(path: &[u8]) -> Option<Vec<u8>> {
+    let mut unescaped = Vec::with_capacity(path.len());
+    let mut bytes = path.iter();
+    while let Some(&b) = bytes.next() {
+        if b != b'\\' {
+            unescaped.push(b);
+            continue;
+        }
+        match bytes.next()? {
+            b'\\' => unescaped.push(b'\\'),
+            b'n' => unescaped.push(b'\n'),
+            b'r' => unescaped.push(b'\r'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+/// Guesses the algorithm used by the coreutils tools from the length of a hex digest.
+pub fn infer_coreutils_hash(hex_len: usize) -> Option<HashType> {
+    match hex_len {
+        32 => Some(HashType::Md5),
+        40 => Some(HashType::Sha1),
+        56 => Some(HashType::Sha224),
+        64 => Some(HashType::Sha256),
+        96 => Some(HashType::Sha384),
+        128 => Some(HashType::Sha512),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef";
+
+    fn entry(format: Format, hashes: &[HashType], path: &str, hash: &str) -> String {
+        let hash_data = HashData::with_hash(PathBuf::from(path), Some(hash.to_owned()));
+        format.entry(hashes, &[], &hash_data)
+    }
+
+    #[test]
+    fn coreutils_entries_round_trip() {
+        for path in [
+            "plain.txt",
+            "two  spaces",
+            "*star",
+            "back\\slash",
+            "line\nbreak\r",
+        ] {
+            let line = entry(Format::Coreutils, &[HashType::Md5], path, HASH);
+            let line = line.strip_suffix('\n').unwrap();
+            assert!(!line.contains('\n'), "{line:?}");
+            let (hash, parsed) = parse_coreutils(line.as_bytes()).unwrap();
+            assert_eq!((hash.as_str(), parsed), (HASH, PathBuf::from(path)));
+        }
+    }
+
+    #[test]
+    fn bsd_entries_round_trip() {
+        let hashes = [HashType::Md5, HashType::Blake2b(256)];
+        let values = format!("{HASH},{HASH}");
+        for path in ["plain.txt", "paren) = x", "back\\slash", "line\nbreak"] {
+            let lines = entry(Format::Bsd, &hashes, path, &values);
+            let parsed: Vec<_> = lines
+                .lines()
+                .map(|line| parse_bsd(line.as_bytes()).unwrap())
+                .collect();
+            assert_eq!(parsed.len(), 2);
+            for ((hash, parsed_path, value), expected) in parsed.into_iter().zip(&hashes) {
+                assert_eq!(&hash, expected);
+                assert_eq!(parsed_path, PathBuf::from(path));
+                assert_eq!(value, HASH);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_only_recorded_by_native() {
+        let path = path_from_bytes(b"bad\xffname".to_vec()).unwrap();
+        assert!(Format::Native.unrepresentable(&path).is_none());
+        for format in [
+            Format::Coreutils,
+            Format::Bsd,
+            Format::Hashdeep,
+            Format::Dfxml,
+            Format::Jsonl,
+            Format::Csv(csv::parse_columns("").unwrap()),
+        ] {
+            assert!(format.unrepresentable(&path).is_some(), "{format}");
+        }
+    }
+
+    #[test]
+    fn hashdeep_paths_cannot_break_lines() {
+        for path in ["line\nbreak", "carriage\rreturn"] {
+            assert!(Format::Hashdeep.unrepresentable(Path::new(path)).is_some());
+            assert!(Format::Coreutils.unrepresentable(Path::new(path)).is_none());
+        }
+    }
+
+    #[test]
+    fn coreutils_lines_are_parsed() {
+        let text = format!("{HASH}  dir/file");
+        assert_eq!(
+            parse_coreutils(text.as_bytes()),
+            Some((HASH.to_owned(), PathBuf::from("dir/file")))
+        );
+        let binary = format!("{} *file", HASH.to_uppercase());
+        assert_eq!(
+            parse_coreutils(binary.as_bytes()),
+            Some((HASH.to_owned(), PathBuf::from("file")))
+        );
+        let escaped = format!("\\{HASH}  a\\\\b\\nc");
+        assert_eq!(
+            parse_coreutils(escaped.as_bytes()).unwrap().1,
+            PathBuf::from("a\\b\nc")
+        );
+        assert_eq!(parse_coreutils(format!("{HASH} file").as_bytes()), None);
+        assert_eq!(parse_coreutils(b"not-hex  file"), None);
+        assert_eq!(
+            parse_coreutils(format!("\\{HASH}  bad\\escape").as_bytes()),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn coreutils_paths_keep_raw_bytes() {
+        let mut line = format!("{HASH}  bad").into_bytes();
+        line.extend_from_slice(b"\xffname");
+        let expected = path_from_bytes(b"bad\xffname".to_vec()).unwrap();
+        assert_eq!(parse_coreutils(&line).unwrap().1, expected);
+        let mut line = b"SHA256 (bad\xffname) = ".to_vec();
+        line.extend_from_slice(HASH.as_bytes());
+        assert_eq!(parse_bsd(&line).unwrap().1, expected);
+    }
+
+    #[test]
+    fn bsd_tags_are_parsed() {
+        let line = format!("BLAKE2b (file) = {HASH}");
+        assert_eq!(
+            parse_bsd(line.as_bytes()).unwrap().0,
+            HashType::Blake2b(512)
+        );
+        let line = format!("BLAKE2b-256 (file) = {HASH}");
+        assert_eq!(
+            parse_bsd(line.as_bytes()).unwrap().0,
+            HashType::Blake2b(256)
+        );
+        let line = format!("SHA256 (a (b) = c) = {}", HASH.to_uppercase());
+        assert_eq!(
+            parse_bsd(line.as_bytes()),
+            Some((
+                HashType::Sha256,
+                PathBuf::from("a (b) = c"),
+                HASH.to_owned()
+            ))
+        );
+        assert!(parse_bsd(format!("UNKNOWN (file) = {HASH}").as_bytes()).is_none());
+    }
+}
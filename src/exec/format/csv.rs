@@ -0,0 +1,278 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+use crate::{Error, HashType};
+
+use jiff::Timestamp;
+
+use super::super::{FileTimes, HashData};
+use super::HashValues;
+
+const PATH_COLUMN: &str = "path";
+const SIZE_COLUMN: &str = "size";
+const MTIME_COLUMN: &str = "mtime";
+const HASHES_COLUMN: &str = "hashes";
+/// Columns written when none are selected.
+const DEFAULT_COLUMNS: [Column; 3] = [Column::Path, Column::Size, Column::Hashes];
+/// RFC 4180 record terminator.
+const RECORD_END: &str = "\r\n";
+
+/// A single column of a CSV hashes file.
+#[derive(Clone, PartialEq)]
+pub enum Column {
+    Path,
+    /// Size in bytes, empty for directories.
+    Size,
+    /// Modification time as an RFC 3339 timestamp, only used by quick audits.
+    Mtime,
+    /// Placeholder expanding to a column per selected hash algorithm, in order.
+    Hashes,
+    Hash(HashType),
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            PATH_COLUMN => Ok(Column::Path),
+            SIZE_COLUMN => Ok(Column::Size),
+            MTIME_COLUMN => Ok(Column::Mtime),
+            HASHES_COLUMN => Ok(Column::Hashes),
+            _ => HashType::from_str(s)
+                .map(Column::Hash)
+                .map_err(|_| format!("invalid csv column: {s}, possible options are: path, size, mtime, hashes or a hash algorithm")),
+        }
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Column::Path => write!(f, "{PATH_COLUMN}"),
+            Column::Size => write!(f, "{SIZE_COLUMN}"),
+            Column::Mtime => write!(f, "{MTIME_COLUMN}"),
+            Column::Hashes => write!(f, "{HASHES_COLUMN}"),
+            Column::Hash(hash) => write!(f, "{hash}"),
+        }
+    }
+}
+
+/// Parses a comma separated column selection, an empty one selects the default columns.
+pub fn parse_columns(s: &str) -> Result<Vec<Column>, String> {
+    if s.is_empty() {
+        return Ok(DEFAULT_COLUMNS.to_vec());
+    }
+    let columns: Vec<Column> = s
+        .split(',')
+        .map(Column::from_str)
+        .collect::<Result<_, _>>()?;
+    for (i, column) in columns.iter().enumerate() {
+        if columns[..i].contains(column) {
+            return Err(format!("duplicate csv column: {column}"));
+        }
+    }
+    if !columns.contains(&Column::Path) {
+        return Err("the csv columns must include path".to_owned());
+    }
+    Ok(columns)
+}
+
+/// Fails unless every selected algorithm has exactly one column.
+pub fn validate(columns: &[Column], hashes: &[HashType]) -> Result<(), Error> {
+    let selected: Vec<&HashType> = columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Hash(hash) => Some(hash),
+            _ => None,
+        })
+        .collect();
+    if let Some(hash) = selected.iter().find(|hash| !hashes.contains(hash)) {
+        return Err(Error::Unsupported(format!(
+            "the csv column {hash} is not one of the selected hash algorithms"
+        )));
+    }
+    if !columns.contains(&Column::Hashes)
+        && let Some(hash) = hashes.iter().find(|hash| !selected.contains(hash))
+    {
+        return Err(Error::Unsupported(format!(
+            "the hash algorithm {hash} has no csv column, select it or use the hashes column"
+        )));
+    }
+    if columns.contains(&Column::Hashes) && !selected.is_empty() {
+        return Err(Error::Unsupported(
+            "the hashes csv column cannot be combined with single hash algorithm columns"
+                .to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Formats the header record, with the hashes placeholder expanded.
+pub fn header(columns: &[Column], hashes: &[HashType]) -> String {
+    let names: Vec<String> = expand(columns, hashes)
+        .iter()
+        .map(|column| quote(&column.to_string()))
+        .collect();
+    format!("{}{RECORD_END}", names.join(","))
+}
+
+/// Formats the record describing `hash_data`, whose path is `path`, directories have empty
+/// size and hash fields.
+pub fn entry(columns: &[Column], hashes: &[HashType], hash_data: &HashData, path: &str) -> String {
+    let values: Vec<&str> = hash_data
+        .hash()
+        .map(|hash| hash.split(',').collect())
+        .unwrap_or_default();
+    let fields: Vec<String> = expand(columns, hashes)
+        .iter()
+        .map(|column| match column {
+            Column::Path => quote(path),
+            Column::Size => hash_data
+                .size
+                .map(|size| size.to_string())
+                .unwrap_or_default(),
+            Column::Mtime => hash_data
+                .times
+                .and_then(|times| times.modified)
+                .map(|time| time.to_string())
+                .unwrap_or_default(),
+            Column::Hash(hash) => hashes
+                .iter()
+                .position(|selected| selected == hash)
+                .and_then(|i| values.get(i))
+                .map(|value| value.to_string())
+                .unwrap_or_default(),
+            Column::Hashes => unreachable!("hashes column is expanded"),
+        })
+        .collect();
+    format!("{}{RECORD_END}", fields.join(","))
+}
+
+fn expand(columns: &[Column], hashes: &[HashType]) -> Vec<Column> {
+    columns
+        .iter()
+        .flat_map(|column| match column {
+            Column::Hashes => hashes.iter().cloned().map(Column::Hash).collect(),
+            _ => vec![column.clone()],
+        })
+        .collect()
+}
+
+/// Parses a header record, which must name the path and at least one hash algorithm.
+pub fn parse_header(record: &str) -> Option<Vec<Column>> {
+    let columns: Vec<Column> = split_record(record)?
+        .iter()
+        .map(|name| Column::from_str(name).ok())
+        .collect::<Option<_>>()?;
+    let has_hash = columns
+        .iter()
+        .any(|column| matches!(column, Column::Hash(_)));
+    (columns.contains(&Column::Path) && has_hash && !columns.contains(&Column::Hashes))
+        .then_some(columns)
+}
+
+/// Parses a record written by [`entry`]. The digests are returned separately, in column
+/// order, and are `None` for directories.
+pub fn parse(columns: &[Column], record: &str) -> Option<(HashData, Option<HashValues>)> {
+    let fields = split_record(record)?;
+    if fields.len() != columns.len() {
+        return None;
+    }
+    let mut path = None;
+    let mut size = None;
+    let mut modified = None;
+    let mut values = vec![];
+    for (column, field) in columns.iter().zip(fields) {
+        match column {
+            Column::Path if !field.is_empty() => path = Some(PathBuf::from(field)),
+            Column::Size if !field.is_empty() => size = Some(field.parse::<u64>().ok()?),
+            Column::Mtime if !field.is_empty() => modified = Some(field.parse::<Timestamp>().ok()?),
+            Column::Hash(hash) if !field.is_empty() => {
+                if !field.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                values.push((hash.clone(), field.to_ascii_lowercase()));
+            }
+            Column::Path | Column::Hashes => return None,
+            Column::Size | Column::Mtime | Column::Hash(_) => (),
+        }
+    }
+    let mut hash_data = HashData::new(path?);
+    hash_data.size = size;
+    hash_data.times = modified.map(|modified| FileTimes {
+        modified: Some(modified),
+        ..Default::default()
+    });
+    Some((hash_data, (!values.is_empty()).then_some(values)))
+}
+
+/// Whether `record` ends inside a quoted field, meaning it continues on the next line.
+pub fn is_record_open(record: &[u8]) -> bool {
+    record.iter().filter(|&&b| b == b'"').count() % 2 == 1
+}
+
+/// Quotes a field when it holds a separator, a quote or a line break, doubling its quotes.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn split_record(record: &str) -> Option<Vec<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = record.chars().peekable();
+    loop {
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => break,
+                    c => field.push(c),
+                }
+            }
+        }
+        match chars.next() {
+            Some(',') => fields.push(std::mem::take(&mut field)),
+            Some(c) => field.push(c),
+            None => {
+                fields.push(field);
+                return Some(fields);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_round_trip() {
+        let fields = [
+            "plain",
+            "comma,",
+            "\"quoted\"",
+            "line\nbreak",
+            "cr\r\nlf",
+            "",
+            "\"",
+        ];
+        let record: Vec<String> = fields.iter().map(|field| quote(field)).collect();
+        let record = record.join(",");
+        assert!(!is_record_open(record.as_bytes()));
+        assert_eq!(split_record(&record).unwrap(), fields);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_open() {
+        assert!(is_record_open(b"\"line"));
+        assert!(split_record("\"line").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use std::env;
+
+use jiff::{Timestamp, Zoned};
+
+use crate::HashType;
+
+use super::super::HashData;
+
+const INDENT: &str = "  ";
+
+/// Formats the document opening and the `<creator>` block describing this execution.
+pub fn header(start: &Zoned) -> String {
+    let command_line: Vec<String> = env::args().collect();
+    format!(
+        "<?xml version='1.0' encoding='UTF-8'?>\n\
+        <dfxml xmloutputversion='1.0' xmlns='http://www.forensicswiki.org/wiki/Category:Digital_Forensics_XML' xmlns:dc='http://purl.org/dc/elements/1.1/'>\n\
+        {INDENT}<metadata>\n\
+        {INDENT}{INDENT}<dc:type>Hash List</dc:type>\n\
+        {INDENT}</metadata>\n\
+        {INDENT}<creator version='1.0'>\n\
+        {INDENT}{INDENT}<program>{}</program>\n\
+        {INDENT}{INDENT}<version>{}</version>\n\
+        {INDENT}{INDENT}<execution_environment>\n\
+        {INDENT}{INDENT}{INDENT}<command_line>{}</command_line>\n\
+        {INDENT}{INDENT}{INDENT}<start_time>{}</start_time>\n\
+        {INDENT}{INDENT}</execution_environment>\n\
+        {INDENT}</creator>\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        escape(&command_line.join(" ")),
+        start.strftime("%Y-%m-%dT%H:%M:%S%:z"),
+    )
+}
+
+/// Formats a `<fileobject>` element for `hash_data`, whose path is `path`, empty directories
+/// are marked with a `d` name type.
+pub fn entry(hashes: &[HashType], hash_data: &HashData, path: &str) -> String {
+    let mut entry = format!(
+        "{INDENT}<fileobject>\n{INDENT}{INDENT}<filename>{}</filename>\n",
+        escape(path)
+    );
+    if hash_data.hash().is_none() {
+        entry.push_str(&format!("{INDENT}{INDENT}<name_type>d</name_type>\n"));
+    }
+    if let Some(size) = hash_data.size {
+        entry.push_str(&format!("{INDENT}{INDENT}<filesize>{size}</filesize>\n"));
+    }
+    if let Some(times) = &hash_data.times {
+        let elements = [
+            ("mtime", times.modified),
+            ("ctime", times.changed),
+            ("atime", times.accessed),
+            ("crtime", times.created),
+        ];
+        for (name, time) in elements {
+            if let Some(time) = time {
+                entry.push_str(&format!(
+                    "{INDENT}{INDENT}<{name}>{}</{name}>\n",
+                    format_time(time)
+                ));
+            }
+        }
+    }
+    if let Some(values) = hash_data.hash() {
+        for (hash, value) in hashes.iter().zip(values.split(',')) {
+            entry.push_str(&format!(
+                "{INDENT}{INDENT}<hashdigest type='{hash}'>{value}</hashdigest>\n"
+            ));
+        }
+    }
+    entry.push_str(&format!("{INDENT}</fileobject>\n"));
+    entry
+}
+
+/// Formats the document closing.
+pub fn footer() -> String {
+    "</dfxml>\n".to_owned()
+}
+
+fn format_time(time: Timestamp) -> String {
+    time.strftime("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Control characters are not allowed in XML 1.0, they are written as `\xNN` instead.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\'' => escaped.push_str("&apos;"),
+            '"' => escaped.push_str("&quot;"),
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {
+                escaped.push_str(&format!("\\x{:02x}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
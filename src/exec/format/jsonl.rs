@@ -0,0 +1,62 @@
+use std::{path::PathBuf, str::FromStr};
+
+use serde_json::{Map, Value, json};
+
+use crate::HashType;
+
+use super::super::HashData;
+use super::HashValues;
+
+const PATH_KEY: &str = "path";
+const TYPE_KEY: &str = "type";
+const SIZE_KEY: &str = "size";
+const HASHES_KEY: &str = "hashes";
+const FILE_TYPE: &str = "file";
+const DIR_TYPE: &str = "dir";
+
+/// Formats `hash_data`, whose path is `path`, as a single JSON object, digests are keyed by
+/// algorithm.
+pub fn entry(hashes: &[HashType], hash_data: &HashData, path: &str) -> String {
+    let mut object = Map::new();
+    object.insert(PATH_KEY.to_owned(), json!(path));
+    let kind = match hash_data.hash() {
+        Some(_) => FILE_TYPE,
+        None => DIR_TYPE,
+    };
+    object.insert(TYPE_KEY.to_owned(), json!(kind));
+    if let Some(size) = hash_data.size {
+        object.insert(SIZE_KEY.to_owned(), json!(size));
+    }
+    if let Some(values) = hash_data.hash() {
+        let digests: Map<String, Value> = hashes
+            .iter()
+            .zip(values.split(','))
+            .map(|(hash, value)| (hash.to_string(), json!(value)))
+            .collect();
+        object.insert(HASHES_KEY.to_owned(), Value::Object(digests));
+    }
+    format!("{}\n", Value::Object(object))
+}
+
+/// Parses a JSON object written by [`entry`]. The digests are returned separately, in the
+/// order they appear, and are `None` for directories.
+pub fn parse(line: &str) -> Option<(HashData, Option<HashValues>)> {
+    let object = serde_json::from_str::<Value>(line).ok()?;
+    let path = PathBuf::from(object.get(PATH_KEY)?.as_str()?);
+    let mut hash_data = HashData::new(path);
+    hash_data.size = match object.get(SIZE_KEY) {
+        Some(size) => Some(size.as_u64()?),
+        None => None,
+    };
+    match object.get(TYPE_KEY)?.as_str()? {
+        DIR_TYPE => Some((hash_data, None)),
+        FILE_TYPE => {
+            let mut values = vec![];
+            for (hash, value) in object.get(HASHES_KEY)?.as_object()? {
+                values.push((HashType::from_str(hash).ok()?, value.as_str()?.to_owned()));
+            }
+            Some((hash_data, Some(values)))
+        }
+        _ => None,
+    }
+}
@@ -1,26 +1,62 @@
+mod attrs;
 mod checker;
+mod filter;
+mod format;
 mod outfile;
+mod sorter;
+mod special;
+mod symlinks;
 
 use crate::hashing::{self, HashType, Hashed};
 use crate::{Error, verbose_print};
+use attrs::{FileAttrs, FileKind, LinkId};
+use jiff::Timestamp;
 use std::fmt::Display;
 use std::{
-    collections::VecDeque,
-    path::{Path, PathBuf},
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fs::Metadata,
+    io,
+    path::{Component, Path, PathBuf},
     sync::{
-        Mutex,
+        Arc, Mutex,
         atomic::{AtomicBool, Ordering},
     },
+    time::SystemTime,
 };
 
-pub use checker::{Checker, load_check_file};
+pub use attrs::Attr;
+pub use checker::{AuditCounts, AuditOutcome, AuditSource, Checker, load_check_file};
+use filter::Ignores;
+pub use filter::{Filter, Patterns};
+pub use format::Format;
 pub use outfile::OutFile;
+pub use special::SpecialFiles;
+pub use symlinks::Symlinks;
 
 const NO_DATE_STR: &str = "[NO DATE]";
 const TIME_START_STR: &str = "time_start";
 const TIME_FINISH_STR: &str = "time_finish";
 const VERSION_STR: &str = concat!(env!("CARGO_PKG_NAME"), " - version");
 const HASH_ALGO_STR: &str = "algo";
+const NON_CRYPTO_STR: &str = "non_cryptographic";
+const FORMAT_STR: &str = "format";
+const ATTRS_STR: &str = "attrs";
+const BASE_STR: &str = "base";
+const EXCLUDE_STR: &str = "exclude";
+const INCLUDE_STR: &str = "include";
+const IGNORE_FILES_STR: &str = "ignore_files";
+const SYMLINKS_STR: &str = "symlinks";
+const ONE_FILE_SYSTEM_STR: &str = "one_file_system";
+const SPECIAL_FILES_STR: &str = "special_files";
+const HASH_BLOCK_DEVICES_STR: &str = "hash_block_devices";
+/// Layout version of the native hashes files, files without a `format` line are version 1.
+/// Version 2 escapes the paths, see [`escape_path`], and version 3 adds the `attrs` line
+/// listing the attributes recorded after the hashes of every entry.
+const FORMAT_VERSION: u32 = 3;
+
+/// Most hashes of hardlinked files kept in memory at once.
+const MAX_LINKED_HASHES: usize = 1 << 18;
 
 static CANCEL: AtomicBool = AtomicBool::new(false);
 
@@ -37,23 +73,171 @@ fn path_string(path: &Path) -> String {
     path.to_string_lossy().to_string()
 }
 
-pub struct HashData(PathBuf, Option<String>);
+/// Escapes a path for the native format so that every path round-trips exactly: backslashes
+/// and line breaks become `\\`, `\n` and `\r`, and bytes that are not valid UTF-8 `\xHH`.
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path_bytes(path).utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                _ => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    escaped
+}
+
+fn unescape_path(escaped: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            '\\' => bytes.push(b'\\'),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return None;
+                }
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Timestamps of a path, each one only present if the platform provides it.
+#[derive(Clone, Copy, Default)]
+pub struct FileTimes {
+    modified: Option<Timestamp>,
+    accessed: Option<Timestamp>,
+    /// Inode change time.
+    changed: Option<Timestamp>,
+    created: Option<Timestamp>,
+}
+
+impl FileTimes {
+    fn from_metadata(metadata: &Metadata) -> Self {
+        let timestamp = |time: io::Result<SystemTime>| {
+            time.ok().and_then(|time| Timestamp::try_from(time).ok())
+        };
+        Self {
+            modified: timestamp(metadata.modified()),
+            accessed: timestamp(metadata.accessed()),
+            changed: changed_time(metadata),
+            created: timestamp(metadata.created()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn changed_time(metadata: &Metadata) -> Option<Timestamp> {
+    use std::os::unix::fs::MetadataExt;
+
+    Timestamp::new(metadata.ctime(), metadata.ctime_nsec() as i32).ok()
+}
+
+#[cfg(not(unix))]
+fn changed_time(_metadata: &Metadata) -> Option<Timestamp> {
+    None
+}
+
+pub struct HashData {
+    path: PathBuf,
+    /// Comma separated hash values, `None` for an empty directory.
+    hash: Option<String>,
+    /// Size in bytes, only available for files and formats that record it.
+    size: Option<u64>,
+    /// Available while hashing, hashes files only record the selected attributes.
+    times: Option<FileTimes>,
+    /// Available while hashing, hashes files only record the selected attributes.
+    attrs: Option<FileAttrs>,
+}
 
 impl HashData {
     fn new(path: PathBuf) -> Self {
-        Self(path, None)
+        Self {
+            path,
+            hash: None,
+            size: None,
+            times: None,
+            attrs: None,
+        }
+    }
+
+    fn with_hash(path: PathBuf, hash: Option<String>) -> Self {
+        Self {
+            path,
+            hash,
+            size: None,
+            times: None,
+            attrs: None,
+        }
     }
 
     fn path(&self) -> &Path {
-        &self.0
+        &self.path
+    }
+
+    fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
     }
 
     fn push_hash(&mut self, hash_str: String) {
-        match &mut self.1 {
+        match &mut self.hash {
             Some(hashes) => hashes.push_str(format!(",{hash_str}").as_str()),
-            None => self.1 = Some(hash_str),
+            None => self.hash = Some(hash_str),
         }
     }
+
+    /// Whether both entries describe the same content. Sizes are only compared when both
+    /// entries have one.
+    fn matches(&self, other: &HashData) -> bool {
+        self.hash == other.hash
+            && match (self.size, other.size) {
+                (Some(size), Some(other_size)) => size == other_size,
+                _ => true,
+            }
+    }
 }
 
 impl Display for HashData {
@@ -61,26 +245,48 @@ impl Display for HashData {
         write!(
             f,
             "{}|{}",
-            self.0.to_string_lossy(),
-            self.1.as_deref().unwrap_or("")
+            escape_path(&self.path),
+            self.hash().unwrap_or("")
         )
     }
 }
 
 impl HashData {
-    fn try_from_string(value: String, empty_dirs: bool) -> Result<Self, Error> {
-        let (path, hash) = value.split_once('|').ok_or(Error::FileFormat)?;
-        let path = PathBuf::from(path);
+    /// Parses a native format entry line, `escaped` tells whether the path is escaped, as in
+    /// format version 2. Hashes never contain a `|`, so the last one ends the path.
+    fn try_from_string(value: String, empty_dirs: bool, escaped: bool) -> Result<Self, Error> {
+        let (path, hash) = if escaped {
+            let (path, hash) = value.rsplit_once('|').ok_or(Error::FileFormat)?;
+            (unescape_path(path).ok_or(Error::FileFormat)?, hash)
+        } else {
+            let (path, hash) = value.split_once('|').ok_or(Error::FileFormat)?;
+            (PathBuf::from(path), hash)
+        };
         match (hash.is_empty(), empty_dirs) {
-            (true, true) => Ok(Self(path, None)),
+            (true, true) => Ok(Self::new(path)),
             (true, false) => Err(Error::AuditEmptyDir(path_string(&path))),
-            (false, _) => Ok(Self(path, Some(hash.to_owned()))),
+            (false, _) => Ok(Self::with_hash(path, Some(hash.to_owned()))),
         }
     }
 }
 
+/// What to do with a file before hashing it, decided from its metadata.
+pub enum Precheck {
+    Hash,
+    /// Skip hashing, the size already differs from the hashes file.
+    Differs,
+    /// Skip hashing, the size and modification time are unchanged so the recorded hash is
+    /// assumed.
+    Unchanged(String),
+}
+
 pub trait HashHandler {
     fn handle(&self, hash_data: HashData) -> Result<(), Error>;
+
+    /// Called with the size and timestamps of every file before hashing it.
+    fn precheck(&self, _hash_data: &HashData) -> Precheck {
+        Precheck::Hash
+    }
 }
 
 pub fn run<T: HashHandler>(
@@ -89,28 +295,119 @@ pub fn run<T: HashHandler>(
     empty_dirs: bool,
     handler: T,
 ) -> Result<(), Error> {
-    while let Some(path) = queue.pop_front() {
+    let mut hashers = hashing::new_hashers(hashes);
+    while let Some(pending) = queue.pop_front() {
+        let path = &pending.path;
         if is_canceled() {
             return Ok(());
         }
-        if path.is_dir() {
+        let metadata = match queue.traversal().symlinks {
+            Symlinks::Follow => path.metadata(),
+            Symlinks::Skip | Symlinks::Record => path.symlink_metadata(),
+        };
+        if let Err(err) = &metadata
+            && path.symlink_metadata().is_ok_and(|link| link.is_symlink())
+        {
+            eprintln!("WARNING: skipping symlink {path:?}, it cannot be followed: {err}");
+            continue;
+        }
+        let metadata = metadata.map_err(|err| Error::Io((err, path_string(path))));
+        let metadata = cancel_on_err(metadata)?;
+        if metadata.is_symlink() {
+            if queue.traversal().symlinks == Symlinks::Skip {
+                verbose_print(|| format!("skipping symlink: {:?}", &path), true);
+                continue;
+            }
+            verbose_print(|| format!("recording symlink: {:?}", &path), true);
+            let target = path
+                .read_link()
+                .map_err(|err| Error::Io((err, path_string(path))));
+            let mut hash_data = HashData::new(queue.relative(path));
+            hash_data.hash = Some(symlinks::target_entry(&cancel_on_err(target)?));
+            hash_data.times = Some(FileTimes::from_metadata(&metadata));
+            hash_data.attrs = Some(FileAttrs::from_metadata(&metadata));
+            cancel_on_err(handler.handle(hash_data))?;
+        } else if metadata.is_dir() {
+            if let Some(ancestors) = &pending.ancestors {
+                if ancestors.contains(&metadata) {
+                    eprintln!("WARNING: not descending into {path:?}, a symlink loops back to it");
+                    continue;
+                }
+                if queue.traversal().one_file_system && !ancestors.is_same_device(&metadata) {
+                    verbose_print(|| format!("skipping mount point: {path:?}"), true);
+                    continue;
+                }
+            }
             verbose_print(|| format!("hashing: reading dir {:?}", &path), true);
-            let is_empty = cancel_on_err(queue.push_dir(&path))?;
+            let is_empty = cancel_on_err(queue.push_dir(&pending, &metadata))?;
             if is_empty && empty_dirs {
-                cancel_on_err(handler.handle(HashData(path, None)))?;
+                let mut hash_data = HashData::new(queue.relative(path));
+                hash_data.times = Some(FileTimes::from_metadata(&metadata));
+                hash_data.attrs = Some(FileAttrs::from_metadata(&metadata));
+                cancel_on_err(handler.handle(hash_data))?;
+            }
+        } else if let Some(kind) = queue.special_kind(&metadata) {
+            if queue.traversal().special_files == SpecialFiles::Skip {
+                eprintln!(
+                    "WARNING: skipping {} {path:?}, special files are not hashed",
+                    kind.as_str()
+                );
+                continue;
             }
+            verbose_print(|| format!("recording special file: {:?}", &path), true);
+            let mut hash_data = HashData::new(queue.relative(path));
+            hash_data.hash = Some(special::type_entry(kind));
+            hash_data.times = Some(FileTimes::from_metadata(&metadata));
+            hash_data.attrs = Some(FileAttrs::from_metadata(&metadata));
+            cancel_on_err(handler.handle(hash_data))?;
         } else {
             verbose_print(|| format!("hashing file: {:?}", &path), true);
-            let mut hash_data = HashData::new(path);
-            for hash in hashes {
-                let mut hasher = hashing::new_hasher(hash);
-                let hash = match hashing::hash_file(hash_data.path(), &mut *hasher) {
-                    Ok(Hashed::Value(value)) => Ok(value),
-                    Ok(Hashed::Canceled) => return Ok(()),
-                    Err(err) => Err(Error::Io((err, path_string(hash_data.path())))),
-                };
-                let hash = cancel_on_err(hash)?;
-                hash_data.push_hash(hash);
+            let mut hash_data = HashData::new(queue.relative(path));
+            // The metadata of block devices has no size, it is the amount of data hashed.
+            let is_file = metadata.is_file();
+            hash_data.size = is_file.then_some(metadata.len());
+            hash_data.times = Some(FileTimes::from_metadata(&metadata));
+            hash_data.attrs = Some(FileAttrs::from_metadata(&metadata));
+            let precheck = match is_file {
+                true => handler.precheck(&hash_data),
+                false => Precheck::Hash,
+            };
+            match precheck {
+                Precheck::Hash => match queue.linked_hash(&metadata) {
+                    Some(hash) => {
+                        verbose_print(
+                            || format!("hardlink already hashed, not hashing: {:?}", &path),
+                            true,
+                        );
+                        hash_data.hash = Some(hash);
+                    }
+                    None => {
+                        let hashed = match hashing::hash_file(path, hashes, &mut hashers) {
+                            Ok(Hashed::Value(values, size)) => Ok((values, size)),
+                            Ok(Hashed::Canceled) => return Ok(()),
+                            Err(err) => Err(Error::Io((err, path_string(path)))),
+                        };
+                        let (values, size) = cancel_on_err(hashed)?;
+                        for hash in values {
+                            hash_data.push_hash(hash);
+                        }
+                        hash_data.size.get_or_insert(size);
+                        if let Some(hash) = hash_data.hash() {
+                            queue.insert_linked_hash(&metadata, hash);
+                        }
+                    }
+                },
+                Precheck::Differs => verbose_print(
+                    || format!("size changed, not hashing: {:?}", hash_data.path()),
+                    true,
+                ),
+                Precheck::Unchanged(hash) => {
+                    verbose_print(
+                        || format!("metadata unchanged, not hashing: {:?}", hash_data.path()),
+                        true,
+                    );
+                    hash_data.hash = Some(hash);
+                }
             }
             cancel_on_err(handler.handle(hash_data))?;
         }
@@ -125,13 +422,174 @@ fn cancel_on_err<T, E>(result: Result<T, E>) -> Result<T, E> {
     result
 }
 
-pub struct Queue(Mutex<VecDeque<PathBuf>>);
+/// Rules of the ignore files in `base` and in the directories between it and `input`, so
+/// that traversing `input` skips the same paths as traversing the whole base directory.
+fn base_ignores(filter: &Filter, base: &Path, input: &Path) -> Result<Option<Arc<Ignores>>, Error> {
+    let Some(parent) = input.parent().filter(|_| input != base) else {
+        return Ok(None);
+    };
+    let Ok(relative) = parent.strip_prefix(base) else {
+        return Ok(None);
+    };
+    let mut dir = base.to_path_buf();
+    let mut ignores = filter.load_ignores(&dir, None)?;
+    for component in relative.components() {
+        dir.push(component);
+        ignores = filter.load_ignores(&dir, ignores.as_ref())?;
+    }
+    Ok(ignores)
+}
+
+/// Absolute form of `path` with its `.` and `..` components resolved lexically, so that it
+/// can be compared with the base directory.
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    let absolute = std::path::absolute(path).map_err(|err| Error::Io((err, path_string(path))))?;
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Traversed directory, chained to the directories containing it, used to detect symlinks
+/// pointing back to an ancestor and mount points.
+struct Ancestor {
+    id: Option<(u64, u64)>,
+    /// Device of the input path the traversal started from.
+    root_device: Option<u64>,
+    parent: Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn new(metadata: &Metadata, parent: Option<&Arc<Ancestor>>) -> Self {
+        let id = dir_id(metadata);
+        Self {
+            id,
+            root_device: match parent {
+                Some(parent) => parent.root_device,
+                None => id.map(|(device, _)| device),
+            },
+            parent: parent.cloned(),
+        }
+    }
+
+    /// Whether the directory of `metadata` is this one or one of its ancestors.
+    fn contains(&self, metadata: &Metadata) -> bool {
+        let Some(id) = dir_id(metadata) else {
+            return false;
+        };
+        let mut ancestor = Some(self);
+        while let Some(current) = ancestor {
+            if current.id == Some(id) {
+                return true;
+            }
+            ancestor = current.parent.as_deref();
+        }
+        false
+    }
+
+    /// Whether the directory of `metadata` is on the same device as the traversal root,
+    /// always true if the platform provides no device ids.
+    fn is_same_device(&self, metadata: &Metadata) -> bool {
+        match (dir_id(metadata), self.root_device) {
+            (Some((device, _)), Some(root_device)) => device == root_device,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn link_count(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &Metadata) -> u64 {
+    1
+}
+
+/// Device and inode of a directory, unix only.
+#[cfg(unix)]
+fn dir_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Path waiting in the [`Queue`].
+struct Pending {
+    path: PathBuf,
+    /// Ignore file rules of the directories containing `path`.
+    ignores: Option<Arc<Ignores>>,
+    /// Directories containing `path`, `None` for the input paths.
+    ancestors: Option<Arc<Ancestor>>,
+}
+
+/// How the paths found while traversing are handled.
+#[derive(Clone, Copy)]
+pub struct Traversal {
+    pub symlinks: Symlinks,
+    /// Do not descend into directories on another device than their input path.
+    pub one_file_system: bool,
+    pub special_files: SpecialFiles,
+    /// Hash block devices like regular files instead of applying `special_files`.
+    pub hash_block_devices: bool,
+}
+
+pub struct Queue {
+    paths: Mutex<VecDeque<Pending>>,
+    /// Absolute directory the entries are relative to.
+    base: Option<PathBuf>,
+    /// Applied to the contents of traversed directories, not to the input paths.
+    filter: Filter,
+    traversal: Traversal,
+    /// Cached hashes of the files with several hardlinks, by device and inode, along with
+    /// the number of their links not traversed yet.
+    linked_hashes: Mutex<HashMap<LinkId, (String, u64)>>,
+}
 
 impl Queue {
-    pub fn new(input: &[String], recursive: bool) -> Result<Self, Error> {
+    /// With a `base`, every input must be inside it and an empty input stands for the base
+    /// itself.
+    pub fn new(
+        input: &[String],
+        recursive: bool,
+        base: Option<&Path>,
+        filter: Filter,
+        traversal: Traversal,
+    ) -> Result<Self, Error> {
+        let base = match base {
+            Some(base) => Some(absolute(base)?),
+            None => None,
+        };
+        let mut input = input.to_vec();
+        if let Some(base) = &base
+            && input.is_empty()
+        {
+            input.push(path_string(base));
+        }
         let mut queue = VecDeque::with_capacity(input.len());
-        for path in input {
-            let pathbuf = PathBuf::from(path);
+        for path in &input {
+            let mut pathbuf = PathBuf::from(path);
+            if let Some(base) = &base {
+                pathbuf = absolute(&pathbuf)?;
+                if !pathbuf.starts_with(base) {
+                    return Err(Error::OutsideBase((path.to_owned(), path_string(base))));
+                }
+            }
             if pathbuf
                 .metadata()
                 .map_err(|err| Error::Io((err, path.to_owned())))?
@@ -140,32 +598,192 @@ impl Queue {
             {
                 return Err(Error::IsDir(path.to_owned()));
             }
-            queue.push_back(pathbuf);
+            let ignores = match &base {
+                Some(base) => base_ignores(&filter, base, &pathbuf)?,
+                None => None,
+            };
+            queue.push_back(Pending {
+                path: pathbuf,
+                ignores,
+                ancestors: None,
+            });
         }
-        Ok(Self(Mutex::new(queue)))
+        Ok(Self {
+            paths: Mutex::new(queue),
+            base,
+            filter,
+            traversal,
+            linked_hashes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn base(&self) -> Option<&Path> {
+        self.base.as_deref()
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
     }
 
-    fn pop_front(&self) -> Option<PathBuf> {
-        self.0.lock().unwrap().pop_front()
+    pub fn traversal(&self) -> &Traversal {
+        &self.traversal
     }
 
-    fn push_dir(&self, path: &Path) -> Result<bool, Error> {
+    /// Hash of a file already hashed through another hardlink to the inode of `metadata`,
+    /// if it is still cached.
+    fn linked_hash(&self, metadata: &Metadata) -> Option<String> {
+        let id = attrs::link_id(metadata)?;
+        let mut linked_hashes = self.linked_hashes.lock().unwrap();
+        let (hash, remaining) = linked_hashes.get_mut(&id)?;
+        *remaining = remaining.saturating_sub(1);
+        match *remaining {
+            0 => linked_hashes.remove(&id).map(|(hash, _)| hash),
+            _ => Some(hash.clone()),
+        }
+    }
+
+    /// Caches the hash of a file with several hardlinks. Links outside the traversed paths
+    /// keep entries from ever being evicted by their count, so the whole cache is dropped
+    /// when it is full.
+    fn insert_linked_hash(&self, metadata: &Metadata, hash: &str) {
+        let Some(id) = attrs::link_id(metadata) else {
+            return;
+        };
+        let mut linked_hashes = self.linked_hashes.lock().unwrap();
+        // Another thread hashed a link to the same inode meanwhile, this link is one less
+        // to wait for.
+        if let Some((_, remaining)) = linked_hashes.get_mut(&id) {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                linked_hashes.remove(&id);
+            }
+            return;
+        }
+        if linked_hashes.len() >= MAX_LINKED_HASHES {
+            verbose_print(|| "hardlink hash cache full, clearing it", true);
+            linked_hashes.clear();
+        }
+        linked_hashes.insert(id, (hash.to_owned(), link_count(metadata) - 1));
+    }
+
+    /// Type of a path that is neither a directory nor a file to hash, `metadata` not being
+    /// the one of a symlink.
+    fn special_kind(&self, metadata: &Metadata) -> Option<FileKind> {
+        if metadata.is_file() {
+            return None;
+        }
+        match FileKind::from_file_type(metadata.file_type()) {
+            FileKind::BlockDevice if self.traversal.hash_block_devices => None,
+            kind => Some(kind),
+        }
+    }
+
+    /// Path of an entry as recorded in hashes files, relative to the base if there is one.
+    fn relative(&self, path: &Path) -> PathBuf {
+        match self
+            .base
+            .as_ref()
+            .and_then(|base| path.strip_prefix(base).ok())
+        {
+            Some(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+            Some(relative) => relative.to_path_buf(),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn pop_front(&self) -> Option<Pending> {
+        self.paths.lock().unwrap().pop_front()
+    }
+
+    fn push_dir(&self, dir: &Pending, metadata: &Metadata) -> Result<bool, Error> {
+        let path = &dir.path;
+        let ignores = self.filter.load_ignores(path, dir.ignores.as_ref())?;
+        let ancestors = Arc::new(Ancestor::new(metadata, dir.ancestors.as_ref()));
         let mut is_empty = true;
-        let mut queue = self.0.lock().unwrap();
+        let mut queue = self.paths.lock().unwrap();
         let reader = path
             .read_dir()
             .map_err(|err| Error::Io((err, path_string(path))))?;
         for entry in reader {
-            is_empty = false;
             if is_canceled() {
-                return Ok(is_empty);
+                return Ok(false);
             }
-            queue.push_back(
-                entry
-                    .map_err(|err| Error::Io((err, path_string(path))))?
-                    .path(),
-            );
+            let entry = entry.map_err(|err| Error::Io((err, path_string(path))))?;
+            let entry_path = entry.path();
+            if self.filter.patterns().has_globs() || ignores.is_some() {
+                let file_type = entry
+                    .file_type()
+                    .map_err(|err| Error::Io((err, path_string(&entry_path))))?;
+                let is_dir = file_type.is_dir()
+                    || (self.traversal.symlinks == Symlinks::Follow
+                        && file_type.is_symlink()
+                        && entry_path.is_dir());
+                if !self.filter.is_kept(&self.relative(&entry_path), is_dir) {
+                    verbose_print(|| format!("filtered out: {entry_path:?}"), true);
+                    continue;
+                }
+                if let Some(ignores) = &ignores
+                    && ignores.is_ignored(&entry_path, is_dir)
+                {
+                    verbose_print(|| format!("ignored: {entry_path:?}"), true);
+                    continue;
+                }
+            }
+            is_empty = false;
+            queue.push_back(Pending {
+                path: entry_path,
+                ignores: ignores.clone(),
+                ancestors: Some(ancestors.clone()),
+            });
         }
         Ok(is_empty)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn path_from_raw(bytes: &[u8]) -> PathBuf {
+        path_from_bytes(bytes.to_vec()).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escaped_paths_round_trip() {
+        let names: [&[u8]; 8] = [
+            b"plain/name.txt",
+            b"with|pipe|",
+            b"back\\slash\\",
+            b"line\nbreak",
+            b"carriage\rreturn",
+            b"invalid\xff\xfeutf8",
+            b"escape-like \\n and \\x41",
+            "unicode \u{e9}\u{1f600}".as_bytes(),
+        ];
+        for name in names {
+            let path = path_from_raw(name);
+            let escaped = escape_path(&path);
+            assert!(!escaped.contains(['\n', '\r']), "{escaped}");
+            assert_eq!(unescape_path(&escaped).as_deref(), Some(path.as_path()));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_entries_round_trip() {
+        let path = path_from_raw(b"dir|x/a\\b\nc\xff|");
+        let hash_data = HashData::with_hash(path.clone(), Some("ab,cd".to_owned()));
+        let parsed = HashData::try_from_string(hash_data.to_string(), false, true).unwrap();
+        assert_eq!(parsed.path(), path);
+        assert_eq!(parsed.hash(), Some("ab,cd"));
+    }
+
+    #[test]
+    fn invalid_escapes_are_rejected() {
+        for escaped in ["trailing\\", "unknown\\t", "short\\x4", "not hex\\xzz"] {
+            assert!(unescape_path(escaped).is_none(), "{escaped}");
+        }
+    }
+}
@@ -9,17 +9,41 @@ use std::{
 };
 
 use super::{
-    HASH_ALGO_STR, HashData, HashHandler, NO_DATE_STR, TIME_FINISH_STR, TIME_START_STR, VERSION_STR,
+    ATTRS_STR, Attr, BASE_STR, EXCLUDE_STR, FORMAT_STR, FORMAT_VERSION, Format, HASH_ALGO_STR,
+    HASH_BLOCK_DEVICES_STR, HashData, HashHandler, IGNORE_FILES_STR, INCLUDE_STR, NO_DATE_STR,
+    NON_CRYPTO_STR, ONE_FILE_SYSTEM_STR, Queue, SPECIAL_FILES_STR, SYMLINKS_STR, TIME_FINISH_STR,
+    TIME_START_STR, VERSION_STR, escape_path, path_bytes,
+    sorter::{SPILL_SIZE, Sorter},
 };
 
 type GuardedWriter = Mutex<BufWriter<File>>;
 
 pub struct OutFile {
     writer: GuardedWriter,
+    /// Collects the entries when they are written sorted by path.
+    sorter: Option<Mutex<Sorter>>,
+    hashes: Vec<HashType>,
+    attrs: Vec<Attr>,
+    format: Format,
 }
 
 impl OutFile {
-    pub fn new(path: &Path, hash: &[HashType]) -> Result<Self, Error> {
+    pub fn new(
+        path: &Path,
+        hash: &[HashType],
+        format: Format,
+        attrs: &[Attr],
+        queue: &Queue,
+        empty_dirs: bool,
+        sorted: bool,
+    ) -> Result<Self, Error> {
+        format.validate(
+            hash,
+            attrs,
+            empty_dirs,
+            queue.traversal().symlinks,
+            queue.traversal().special_files,
+        )?;
         verbose_print(|| "creating output file", true);
         let file = OpenOptions::new()
             .read(true)
@@ -29,72 +53,171 @@ impl OutFile {
             .open(path)
             .map_err(Error::OutputRead)?;
         let mut writer = BufWriter::new(file);
-        let version = env!("CARGO_PKG_VERSION");
-        let time = current_time_string();
-        let mut time_str: Vec<u8> = format!(
-            "{VERSION_STR} {version}\n{HASH_ALGO_STR} {}\n{TIME_START_STR} {time} - {TIME_FINISH_STR} ",
-            hash.iter().map(HashType::as_str).collect::<Vec<&str>>().join(",")
-        )
-        .into();
-        time_str.extend(vec![b' '; time.len()]);
-        time_str.push(b'\n');
-        writer.write_all(&time_str).map_err(Error::OutputWrite)?;
+        let start = Zoned::now();
+        match format.header(hash, &start) {
+            Some(header) => writer
+                .write_all(header.as_bytes())
+                .map_err(Error::OutputWrite)?,
+            None if matches!(format, Format::Native) => {
+                write_native_header(&mut writer, hash, attrs, queue, &start)?
+            }
+            None => (),
+        }
         Ok(Self {
             writer: Mutex::new(writer),
+            sorter: sorted.then(|| Mutex::new(Sorter::new(path, SPILL_SIZE))),
+            hashes: hash.to_vec(),
+            attrs: attrs.to_vec(),
+            format,
         })
     }
 
-    pub fn writer(&self) -> &GuardedWriter {
-        &self.writer
-    }
-
     pub fn finish(self) -> Result<(), Error> {
-        verbose_print(|| "writing finish date", true);
-        let writer = self.writer.into_inner().map_err(|_| {
+        let mut writer = self.writer.into_inner().map_err(|_| {
             Error::OutputFinish("failed retrieve outfile bufwriter out of mutex".to_owned())
         })?;
-        let mut file = writer.into_inner().map_err(|_| {
+        if let Some(sorter) = self.sorter {
+            let sorter = sorter.into_inner().map_err(|_| {
+                Error::OutputFinish("failed retrieve sorter out of mutex".to_owned())
+            })?;
+            sorter.finish(&mut writer)?;
+        }
+        if let Some(footer) = self.format.footer() {
+            writer
+                .write_all(footer.as_bytes())
+                .map_err(Error::OutputWrite)?;
+        }
+        let file = writer.into_inner().map_err(|_| {
             Error::OutputFinish("failed to retrieve inner file out of bufwriter".to_owned())
         })?;
-        file.rewind().unwrap();
-        let time_str = current_time_string();
-        let cursor = {
-            let mut reader = BufReader::new(&file);
-            let _ = reader.by_ref().lines().nth(2);
-            reader.stream_position().unwrap() - (time_str.len() + 1) as u64
-        };
-
-        #[cfg(target_os = "windows")]
-        {
-            use std::os::windows::fs::FileExt;
-
-            file.seek_write(time_str.as_bytes(), cursor)
-                .map_err(Error::OutputWrite)?;
+        match self.format {
+            Format::Native => write_finish_time(file),
+            Format::Coreutils
+            | Format::Bsd
+            | Format::Hashdeep
+            | Format::Dfxml
+            | Format::Jsonl
+            | Format::Csv(_) => Ok(()),
         }
+    }
+}
 
-        #[cfg(target_os = "linux")]
-        {
-            use std::os::unix::fs::FileExt;
+fn write_native_header(
+    writer: &mut BufWriter<File>,
+    hash: &[HashType],
+    attrs: &[Attr],
+    queue: &Queue,
+    start: &Zoned,
+) -> Result<(), Error> {
+    let version = env!("CARGO_PKG_VERSION");
+    let time = time_string(start);
+    let mut time_str: Vec<u8> = format!(
+        "{VERSION_STR} {version}\n{HASH_ALGO_STR} {}\n{TIME_START_STR} {time} - {TIME_FINISH_STR} ",
+        hash.iter()
+            .map(HashType::to_string)
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+    .into();
+    time_str.extend(vec![b' '; time.len()]);
+    time_str.push(b'\n');
+    time_str.extend(format!("{FORMAT_STR} {FORMAT_VERSION}\n").bytes());
+    if !attrs.is_empty() {
+        let attrs: Vec<&str> = attrs.iter().map(Attr::as_str).collect();
+        time_str.extend(format!("{ATTRS_STR} {}\n", attrs.join(",")).bytes());
+    }
+    if let Some(base) = queue.base() {
+        time_str.extend(format!("{BASE_STR} {}\n", escape_path(base)).bytes());
+    }
+    let patterns = queue.filter().patterns();
+    for pattern in &patterns.exclude {
+        time_str.extend(format!("{EXCLUDE_STR} {pattern}\n").bytes());
+    }
+    for pattern in &patterns.include {
+        time_str.extend(format!("{INCLUDE_STR} {pattern}\n").bytes());
+    }
+    if !patterns.ignore_files.is_empty() {
+        let names = patterns.ignore_files.join(",");
+        time_str.extend(format!("{IGNORE_FILES_STR} {names}\n").bytes());
+    }
+    time_str.extend(format!("{SYMLINKS_STR} {}\n", queue.traversal().symlinks).bytes());
+    if queue.traversal().one_file_system {
+        time_str.extend(format!("{ONE_FILE_SYSTEM_STR}\n").bytes());
+    }
+    time_str.extend(format!("{SPECIAL_FILES_STR} {}\n", queue.traversal().special_files).bytes());
+    if queue.traversal().hash_block_devices {
+        time_str.extend(format!("{HASH_BLOCK_DEVICES_STR}\n").bytes());
+    }
+    let non_crypto: Vec<String> = hash
+        .iter()
+        .filter(|hash| !hash.is_cryptographic())
+        .map(HashType::to_string)
+        .collect();
+    if !non_crypto.is_empty() {
+        time_str.extend(format!("{NON_CRYPTO_STR} {}\n", non_crypto.join(",")).bytes());
+    }
+    time_str.push(b'\n');
+    writer.write_all(&time_str).map_err(Error::OutputWrite)
+}
 
-            file.write_at(time_str.as_bytes(), cursor)
-                .map_err(Error::OutputWrite)?;
-        }
+fn write_finish_time(mut file: File) -> Result<(), Error> {
+    verbose_print(|| "writing finish date", true);
+    file.rewind().unwrap();
+    let time_str = current_time_string();
+    let cursor = {
+        let mut reader = BufReader::new(&file);
+        let _ = reader.by_ref().lines().nth(2);
+        reader.stream_position().unwrap() - (time_str.len() + 1) as u64
+    };
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::FileExt;
+
+        file.seek_write(time_str.as_bytes(), cursor)
+            .map_err(Error::OutputWrite)?;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::FileExt;
 
-        Ok(())
+        file.write_at(time_str.as_bytes(), cursor)
+            .map_err(Error::OutputWrite)?;
     }
+
+    Ok(())
 }
 
-impl HashHandler for &GuardedWriter {
+impl HashHandler for &OutFile {
     fn handle(&self, hash_data: HashData) -> Result<(), Error> {
-        self.lock()
+        if let Some(reason) = self.format.unrepresentable(hash_data.path()) {
+            eprintln!(
+                "WARNING: skipping {:?}, the {} format cannot record it, {reason}",
+                hash_data.path(),
+                self.format
+            );
+            return Ok(());
+        }
+        let entry = self.format.entry(&self.hashes, &self.attrs, &hash_data);
+        if let Some(sorter) = &self.sorter {
+            let key = path_bytes(hash_data.path()).into_owned();
+            return sorter.lock().unwrap().push(key, entry);
+        }
+        self.writer
+            .lock()
             .unwrap()
-            .write_all(format!("{hash_data}\n").as_bytes())
+            .write_all(entry.as_bytes())
             .map_err(Error::OutputWrite)
     }
 }
 
 fn current_time_string() -> String {
-    match Zoned::now().round(Unit::Second) {
+    time_string(&Zoned::now())
+}
+
+fn time_string(time: &Zoned) -> String {
+    match time.round(Unit::Second) {
         Ok(dt) => dt.datetime().to_string(),
         Err(err) => {
             eprintln!("WARNING: failed to aquire current date: {err}");
@@ -0,0 +1,162 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, verbose_print};
+
+/// Default amount of entry bytes kept in memory before spilling them to a run file.
+pub const SPILL_SIZE: usize = 64 * 1024 * 1024;
+
+/// Collects formatted entries and writes them sorted by path. Entries that do not fit in
+/// memory are spilled as sorted runs to temporary files next to the output, which are merged
+/// at the end.
+pub struct Sorter {
+    buffer: Vec<(Vec<u8>, String)>,
+    buffered_bytes: usize,
+    /// Approximate amount of entry bytes kept in memory before spilling them to a run file.
+    spill_size: usize,
+    /// Prefix of the run file paths, the output path.
+    output: PathBuf,
+    runs: Vec<PathBuf>,
+}
+
+impl Sorter {
+    pub fn new(output: &Path, spill_size: usize) -> Self {
+        Self {
+            buffer: vec![],
+            buffered_bytes: 0,
+            spill_size,
+            output: output.to_path_buf(),
+            runs: vec![],
+        }
+    }
+
+    /// Adds an entry sorted by `key`, the bytes of its path.
+    pub fn push(&mut self, key: Vec<u8>, entry: String) -> Result<(), Error> {
+        self.buffered_bytes += key.len() + entry.len();
+        self.buffer.push((key, entry));
+        if self.buffered_bytes >= self.spill_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<(), Error> {
+        let mut path = self.output.clone().into_os_string();
+        path.push(format!(".sort{}", self.runs.len()));
+        let path = PathBuf::from(path);
+        verbose_print(|| format!("spilling sorted entries to {path:?}"), true);
+        let file = File::create(&path).map_err(Error::OutputWrite)?;
+        self.runs.push(path);
+        let mut writer = BufWriter::new(file);
+        self.buffer.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        for (key, entry) in self.buffer.drain(..) {
+            write_record(&mut writer, &key, &entry).map_err(Error::OutputWrite)?;
+        }
+        self.buffered_bytes = 0;
+        writer.flush().map_err(Error::OutputWrite)
+    }
+
+    /// Writes every entry to `writer` in order.
+    pub fn finish(mut self, writer: &mut impl Write) -> Result<(), Error> {
+        if self.runs.is_empty() {
+            self.buffer.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, entry) in &self.buffer {
+                writer
+                    .write_all(entry.as_bytes())
+                    .map_err(Error::OutputWrite)?;
+            }
+            return Ok(());
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        verbose_print(|| format!("merging {} sorted runs", self.runs.len()), true);
+        let mut readers = Vec::with_capacity(self.runs.len());
+        for path in &self.runs {
+            readers.push(BufReader::new(File::open(path).map_err(Error::OutputRead)?));
+        }
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some((key, entry)) = read_record(reader).map_err(Error::OutputRead)? {
+                heap.push(Reverse((key, i, entry)));
+            }
+        }
+        while let Some(Reverse((_, i, entry))) = heap.pop() {
+            writer
+                .write_all(entry.as_bytes())
+                .map_err(Error::OutputWrite)?;
+            if let Some((key, entry)) = read_record(&mut readers[i]).map_err(Error::OutputRead)? {
+                heap.push(Reverse((key, i, entry)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Sorter {
+    fn drop(&mut self) {
+        for path in &self.runs {
+            if let Err(err) = fs::remove_file(path) {
+                eprintln!("WARNING: failed to remove temporary file {path:?}: {err}");
+            }
+        }
+    }
+}
+
+fn write_record(writer: &mut impl Write, key: &[u8], entry: &str) -> io::Result<()> {
+    writer.write_all(&(key.len() as u64).to_le_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(&(entry.len() as u64).to_le_bytes())?;
+    writer.write_all(entry.as_bytes())
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<Option<(Vec<u8>, String)>> {
+    let mut len = [0; 8];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut key = vec![0; u64::from_le_bytes(len) as usize];
+    reader.read_exact(&mut key)?;
+    reader.read_exact(&mut len)?;
+    let mut entry = vec![0; u64::from_le_bytes(len) as usize];
+    reader.read_exact(&mut entry)?;
+    let entry = String::from_utf8(entry).map_err(io::Error::other)?;
+    Ok(Some((key, entry)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn spilled_runs_are_merged_in_order() {
+        crate::VERBOSE.get_or_init(|| false);
+        let output = env::temp_dir().join(format!("hashgoblin-sorter-{}", std::process::id()));
+        let mut sorter = Sorter::new(&output, 64);
+        let mut expected = vec![];
+        for i in 0..100u32 {
+            let key = format!("{:03}", (i * 37) % 100);
+            sorter
+                .push(key.clone().into_bytes(), format!("{key}|entry\n"))
+                .unwrap();
+            expected.push(format!("{key}|entry\n"));
+        }
+        expected.sort();
+        let runs = sorter.runs.clone();
+        assert!(runs.len() > 2, "{} runs", runs.len());
+        assert!(runs.iter().all(|run| run.exists()));
+        let mut written = vec![];
+        sorter.finish(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), expected.concat());
+        assert!(runs.iter().all(|run| !run.exists()));
+    }
+}
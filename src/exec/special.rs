@@ -0,0 +1,51 @@
+use std::{fmt::Display, str::FromStr};
+
+use super::attrs::FileKind;
+
+/// Start of the hash field of native entries describing a special file, followed by its type.
+const TYPE_STR: &str = "type ";
+
+/// How FIFOs, sockets and device nodes are handled. Reading them may block forever or
+/// never end, so they are not hashed, block devices aside when explicitly requested.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum SpecialFiles {
+    /// Skip them with a warning.
+    #[default]
+    Skip,
+    /// Record their type instead of hashing them, native format only.
+    Record,
+}
+
+impl SpecialFiles {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SpecialFiles::Skip => "skip",
+            SpecialFiles::Record => "record",
+        }
+    }
+}
+
+impl FromStr for SpecialFiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(SpecialFiles::Skip),
+            "record" => Ok(SpecialFiles::Record),
+            _ => Err(format!(
+                "invalid special files policy: {s}, possible options are: skip, record"
+            )),
+        }
+    }
+}
+
+impl Display for SpecialFiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Formats the value recorded in place of the hashes for a special file of type `kind`.
+pub fn type_entry(kind: FileKind) -> String {
+    format!("{TYPE_STR}{}", kind.as_str())
+}
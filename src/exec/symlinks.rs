@@ -0,0 +1,55 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use super::escape_path;
+
+/// Start of the hash field of native entries describing a symlink, followed by its target.
+const TARGET_STR: &str = "-> ";
+
+/// How symlinks found while traversing, input paths included, are handled.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Symlinks {
+    /// Hash what the symlink points to, descending into directories unless that would loop.
+    /// Dangling symlinks are skipped with a warning.
+    #[default]
+    Follow,
+    Skip,
+    /// Record the target path of the symlink instead of hashing it, native format only.
+    Record,
+}
+
+impl Symlinks {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Symlinks::Follow => "follow",
+            Symlinks::Skip => "skip",
+            Symlinks::Record => "record",
+        }
+    }
+}
+
+impl FromStr for Symlinks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "follow" => Ok(Symlinks::Follow),
+            "skip" => Ok(Symlinks::Skip),
+            "record" => Ok(Symlinks::Record),
+            _ => Err(format!(
+                "invalid symlink policy: {s}, possible options are: follow, skip, record"
+            )),
+        }
+    }
+}
+
+impl Display for Symlinks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Formats the value recorded in place of the hashes for a symlink to `target`. The target
+/// is escaped like the paths, with `|` escaped too so that it cannot end the path.
+pub fn target_entry(target: &Path) -> String {
+    format!("{TARGET_STR}{}", escape_path(target).replace('|', "\\x7c"))
+}
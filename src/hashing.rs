@@ -6,71 +6,417 @@ use std::{
     str::FromStr,
 };
 
-use digest::DynDigest;
+use digest::{DynDigest, ExtendableOutputReset, InvalidBufferSize, Reset, Update, VariableOutput};
 
 use crate::exec::is_canceled;
 
-#[derive(Debug)]
+const BUF_SIZE: usize = 64 * 1024;
+/// Files at least this large are hashed with BLAKE3's multi-threaded implementation.
+const PARALLEL_MIN_SIZE: u64 = 16 * 1024 * 1024;
+/// Size of the reads, and of the data handed to BLAKE3 at once, when hashing in parallel.
+const PARALLEL_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+/// Default output length in bits for SHAKE128, matching its 128 bit security level.
+const SHAKE128_DEFAULT_BITS: usize = 256;
+/// Default output length in bits for SHAKE256, matching its 256 bit security level.
+const SHAKE256_DEFAULT_BITS: usize = 512;
+/// Largest output length in bits supported by BLAKE2b, also used as its default.
+const BLAKE2B_MAX_BITS: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum HashType {
     Md5,
+    Sha224,
     Sha256,
+    Sha384,
+    Sha512,
+    Sha512_256,
+    Sha3_256,
+    Sha3_512,
+    /// Holds the output length in bits.
+    Shake128(usize),
+    /// Holds the output length in bits.
+    Shake256(usize),
+    Blake3,
+    /// Holds the output length in bits.
+    Blake2b(usize),
+    Blake2s256,
     Sha1,
     Tiger,
     Whirlpool,
+    Crc32,
+    Crc32c,
+    Xxh64,
+    Xxh3_128,
 }
 
 impl HashType {
     pub fn as_str(&self) -> &str {
         match self {
             HashType::Md5 => "md5",
+            HashType::Sha224 => "sha224",
             HashType::Sha256 => "sha256",
+            HashType::Sha384 => "sha384",
+            HashType::Sha512 => "sha512",
+            HashType::Sha512_256 => "sha512-256",
+            HashType::Sha3_256 => "sha3-256",
+            HashType::Sha3_512 => "sha3-512",
+            HashType::Shake128(_) => "shake128",
+            HashType::Shake256(_) => "shake256",
+            HashType::Blake3 => "blake3",
+            HashType::Blake2b(_) => "blake2b",
+            HashType::Blake2s256 => "blake2s",
             HashType::Sha1 => "sha1",
             HashType::Tiger => "tiger",
             HashType::Whirlpool => "whirlpool",
+            HashType::Crc32 => "crc32",
+            HashType::Crc32c => "crc32c",
+            HashType::Xxh64 => "xxh64",
+            HashType::Xxh3_128 => "xxh3-128",
         }
     }
+
+    /// Checksums only detect accidental corruption, they give no protection against
+    /// deliberate tampering.
+    pub fn is_cryptographic(&self) -> bool {
+        !matches!(
+            self,
+            HashType::Crc32 | HashType::Crc32c | HashType::Xxh64 | HashType::Xxh3_128
+        )
+    }
 }
 
 impl FromStr for HashType {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
+        let s = s.to_lowercase();
+        if let Some((name, bits)) = s.split_once(':') {
+            let bits = parse_output_bits(bits)?;
+            return match name {
+                "shake128" => Ok(HashType::Shake128(bits)),
+                "shake256" => Ok(HashType::Shake256(bits)),
+                "blake2b" if bits <= BLAKE2B_MAX_BITS => Ok(HashType::Blake2b(bits)),
+                "blake2b" => Err(format!(
+                    "invalid output length: {bits}, blake2b supports at most {BLAKE2B_MAX_BITS} bits"
+                )),
+                _ => Err(format!(
+                    "invalid hash: {s}, only shake128, shake256 and blake2b accept an output length"
+                )),
+            };
+        }
+        match s.as_str() {
             "md5" => Ok(HashType::Md5),
             "sha1" => Ok(HashType::Sha1),
+            "sha224" => Ok(HashType::Sha224),
             "sha256" => Ok(HashType::Sha256),
+            "sha384" => Ok(HashType::Sha384),
+            "sha512" => Ok(HashType::Sha512),
+            "sha512-256" | "sha512/256" => Ok(HashType::Sha512_256),
+            "sha3-256" => Ok(HashType::Sha3_256),
+            "sha3-512" => Ok(HashType::Sha3_512),
+            "shake128" => Ok(HashType::Shake128(SHAKE128_DEFAULT_BITS)),
+            "shake256" => Ok(HashType::Shake256(SHAKE256_DEFAULT_BITS)),
+            "blake3" => Ok(HashType::Blake3),
+            "blake2b" | "blake2b-512" => Ok(HashType::Blake2b(BLAKE2B_MAX_BITS)),
+            "blake2s" | "blake2s-256" => Ok(HashType::Blake2s256),
             "tiger" => Ok(HashType::Tiger),
             "whirlpool" => Ok(HashType::Whirlpool),
+            "crc32" => Ok(HashType::Crc32),
+            "crc32c" => Ok(HashType::Crc32c),
+            "xxh64" => Ok(HashType::Xxh64),
+            "xxh3-128" | "xxh128" => Ok(HashType::Xxh3_128),
             _ => Err(format!(
-                "invalid hash: {s}, possible options are: sha256, tiger, whirlpool, sha1, md5"
+                "invalid hash: {s}, possible options are: sha224, sha256, sha384, sha512, sha512-256, sha3-256, sha3-512, shake128[:BITS], shake256[:BITS], blake3, blake2b[:BITS], blake2s, tiger, whirlpool, sha1, md5, crc32, crc32c, xxh64, xxh3-128"
             )),
         }
     }
 }
 
+fn parse_output_bits(bits: &str) -> Result<usize, String> {
+    match bits.parse::<usize>() {
+        Ok(bits) if bits > 0 && bits % 8 == 0 => Ok(bits),
+        _ => Err(format!(
+            "invalid output length: {bits}, it must be a positive multiple of 8 bits"
+        )),
+    }
+}
+
 impl Display for HashType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_str())
+        match self {
+            HashType::Shake128(bits) | HashType::Shake256(bits) | HashType::Blake2b(bits) => {
+                write!(f, "{}:{bits}", self.as_str())
+            }
+            _ => write!(f, "{}", self.as_str()),
+        }
     }
 }
 
 pub fn new_hasher(hash: &HashType) -> Box<dyn DynDigest> {
     match hash {
         HashType::Md5 => Box::new(md5::Md5::default()),
+        HashType::Sha224 => Box::new(sha2::Sha224::default()),
         HashType::Sha256 => Box::new(sha2::Sha256::default()),
+        HashType::Sha384 => Box::new(sha2::Sha384::default()),
+        HashType::Sha512 => Box::new(sha2::Sha512::default()),
+        HashType::Sha512_256 => Box::new(sha2::Sha512_256::default()),
+        HashType::Sha3_256 => Box::new(sha3::Sha3_256::default()),
+        HashType::Sha3_512 => Box::new(sha3::Sha3_512::default()),
+        HashType::Shake128(bits) => Box::new(Xof::new(sha3::Shake128::default(), *bits)),
+        HashType::Shake256(bits) => Box::new(Xof::new(sha3::Shake256::default(), *bits)),
+        HashType::Blake3 => Box::new(blake3::Hasher::new()),
+        HashType::Blake2b(BLAKE2B_MAX_BITS) => Box::new(blake2::Blake2b512::default()),
+        HashType::Blake2b(bits) => Box::new(VarOutput(
+            blake2::Blake2bVar::new(bits / 8).expect("output length is validated on parse"),
+        )),
+        HashType::Blake2s256 => Box::new(blake2::Blake2s256::default()),
         HashType::Sha1 => Box::new(sha1::Sha1::default()),
         HashType::Tiger => Box::new(tiger::Tiger::default()),
         HashType::Whirlpool => Box::new(whirlpool::Whirlpool::default()),
+        HashType::Crc32 => Box::new(Checksum(crc32fast::Hasher::new())),
+        HashType::Crc32c => Box::new(Checksum(Crc32c(0))),
+        HashType::Xxh64 => Box::new(Checksum(xxhash_rust::xxh64::Xxh64::new(0))),
+        HashType::Xxh3_128 => Box::new(Checksum(xxhash_rust::xxh3::Xxh3::new())),
+    }
+}
+
+/// Non-cryptographic checksum state, its value is encoded as big-endian bytes.
+trait ChecksumState: Clone + 'static {
+    const SIZE: usize;
+
+    fn update(&mut self, data: &[u8]);
+    fn value(&self) -> Vec<u8>;
+    fn reset(&mut self);
+}
+
+impl ChecksumState for crc32fast::Hasher {
+    const SIZE: usize = 4;
+
+    fn update(&mut self, data: &[u8]) {
+        crc32fast::Hasher::update(self, data);
+    }
+
+    fn value(&self) -> Vec<u8> {
+        self.clone().finalize().to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        crc32fast::Hasher::reset(self);
+    }
+}
+
+#[derive(Clone)]
+struct Crc32c(u32);
+
+impl ChecksumState for Crc32c {
+    const SIZE: usize = 4;
+
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, data);
+    }
+
+    fn value(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        self.0 = 0;
+    }
+}
+
+impl ChecksumState for xxhash_rust::xxh64::Xxh64 {
+    const SIZE: usize = 8;
+
+    fn update(&mut self, data: &[u8]) {
+        xxhash_rust::xxh64::Xxh64::update(self, data);
+    }
+
+    fn value(&self) -> Vec<u8> {
+        self.digest().to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        xxhash_rust::xxh64::Xxh64::reset(self, 0);
+    }
+}
+
+impl ChecksumState for xxhash_rust::xxh3::Xxh3 {
+    const SIZE: usize = 16;
+
+    fn update(&mut self, data: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, data);
+    }
+
+    fn value(&self) -> Vec<u8> {
+        self.digest128().to_be_bytes().to_vec()
+    }
+
+    fn reset(&mut self) {
+        xxhash_rust::xxh3::Xxh3::reset(self);
+    }
+}
+
+/// Adapts a [`ChecksumState`] to [`DynDigest`].
+#[derive(Clone)]
+struct Checksum<T>(T);
+
+impl<T: ChecksumState> DynDigest for Checksum<T> {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize_into(mut self, buf: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        self.finalize_into_reset(buf)
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        if out.len() != T::SIZE {
+            return Err(InvalidBufferSize);
+        }
+        out.copy_from_slice(&self.0.value());
+        self.0.reset();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn output_size(&self) -> usize {
+        T::SIZE
+    }
+
+    fn box_clone(&self) -> Box<dyn DynDigest> {
+        Box::new(self.clone())
     }
 }
 
+/// Adapts an extendable-output function to [`DynDigest`] by fixing its output length.
+#[derive(Clone)]
+struct Xof<T> {
+    inner: T,
+    len: usize,
+}
+
+impl<T> Xof<T> {
+    fn new(inner: T, bits: usize) -> Self {
+        Self {
+            inner,
+            len: bits / 8,
+        }
+    }
+}
+
+impl<T> DynDigest for Xof<T>
+where
+    T: Update + ExtendableOutputReset + Clone + 'static,
+{
+    fn update(&mut self, data: &[u8]) {
+        Update::update(&mut self.inner, data);
+    }
+
+    fn finalize_into(mut self, buf: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        self.finalize_into_reset(buf)
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        if out.len() != self.len {
+            return Err(InvalidBufferSize);
+        }
+        self.inner.finalize_xof_reset_into(out);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        Reset::reset(&mut self.inner);
+    }
+
+    fn output_size(&self) -> usize {
+        self.len
+    }
+
+    fn box_clone(&self) -> Box<dyn DynDigest> {
+        Box::new(self.clone())
+    }
+}
+
+/// Adapts a hash function whose output length is selected at run time to [`DynDigest`].
+/// Such functions cannot be reset in place, so a fresh instance replaces the finalized one.
+#[derive(Clone)]
+struct VarOutput<T>(T);
+
+impl<T: VariableOutput> VarOutput<T> {
+    fn fresh(&self) -> T {
+        T::new(self.0.output_size()).expect("output size was accepted before")
+    }
+}
+
+impl<T> DynDigest for VarOutput<T>
+where
+    T: Update + VariableOutput + Clone + 'static,
+{
+    fn update(&mut self, data: &[u8]) {
+        Update::update(&mut self.0, data);
+    }
+
+    fn finalize_into(self, buf: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        self.0.finalize_variable(buf)
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut [u8]) -> Result<(), InvalidBufferSize> {
+        let fresh = self.fresh();
+        std::mem::replace(&mut self.0, fresh).finalize_variable(out)
+    }
+
+    fn reset(&mut self) {
+        self.0 = self.fresh();
+    }
+
+    fn output_size(&self) -> usize {
+        VariableOutput::output_size(&self.0)
+    }
+
+    fn box_clone(&self) -> Box<dyn DynDigest> {
+        Box::new(self.clone())
+    }
+}
+
+pub fn new_hashers(hashes: &[HashType]) -> Vec<Box<dyn DynDigest>> {
+    hashes.iter().map(new_hasher).collect()
+}
+
 pub enum Hashed {
-    Value(String),
+    /// Hash values along with the number of bytes hashed.
+    Value(Vec<String>, u64),
     Canceled,
 }
 
-pub fn hash_file(path: &Path, hasher: &mut dyn DynDigest) -> io::Result<Hashed> {
-    let mut reader = BufReader::new(File::open(path)?);
+/// Reads the file at `path` once, feeding every buffer into all of the `hashers`.
+/// The resulting values are returned in the same order as the `hashers`.
+///
+/// For large files any BLAKE3 value is computed using its tree parallelism, reading the file
+/// in larger buffers that are still shared with the remaining `hashers`. Files are read
+/// rather than memory mapped, a mapped file truncated by another process while it is hashed
+/// would kill this process with `SIGBUS`.
+pub fn hash_file(
+    path: &Path,
+    hashes: &[HashType],
+    hashers: &mut [Box<dyn DynDigest>],
+) -> io::Result<Hashed> {
+    let file = File::open(path)?;
+    let parallel =
+        file.metadata()?.len() >= PARALLEL_MIN_SIZE && hashes.contains(&HashType::Blake3);
+    // One multi-threaded hasher per BLAKE3 entry of `hashes`, each one is fed every buffer once.
+    let mut parallel_hashers: Vec<Option<blake3::Hasher>> = hashes
+        .iter()
+        .map(|hash| (parallel && *hash == HashType::Blake3).then(blake3::Hasher::new))
+        .collect();
+    let capacity = match parallel {
+        true => PARALLEL_CHUNK_SIZE,
+        false => BUF_SIZE,
+    };
+    let mut reader = BufReader::with_capacity(capacity, file);
+    let mut size = 0;
     loop {
         if is_canceled() {
             return Ok(Hashed::Canceled);
@@ -80,8 +426,155 @@ pub fn hash_file(path: &Path, hasher: &mut dyn DynDigest) -> io::Result<Hashed>
             break;
         }
         let length = data.len();
-        hasher.update(data);
+        for (hasher, parallel_hasher) in hashers.iter_mut().zip(&mut parallel_hashers) {
+            match parallel_hasher {
+                Some(blake3) => {
+                    blake3.update_rayon(data);
+                }
+                None => hasher.update(data),
+            }
+        }
         reader.consume(length);
+        size += length as u64;
+    }
+    Ok(Hashed::Value(
+        hashers
+            .iter_mut()
+            .zip(&parallel_hashers)
+            .map(|(hasher, parallel_hasher)| match parallel_hasher {
+                Some(blake3) => blake3.finalize().to_hex().to_string(),
+                None => hex::encode(hasher.finalize_reset()),
+            })
+            .collect(),
+        size,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn digest(hash: &HashType, data: &[u8]) -> String {
+        let mut hasher = new_hasher(hash);
+        hasher.update(data);
+        hex::encode(hasher.finalize_reset())
+    }
+
+    #[test]
+    fn repeated_blake3_values_match_on_large_files() {
+        let path = env::temp_dir().join(format!("hashgoblin-blake3-{}", std::process::id()));
+        let data: Vec<u8> = (0..PARALLEL_MIN_SIZE + 1).map(|i| i as u8).collect();
+        fs::write(&path, &data).unwrap();
+        let expected = digest(&HashType::Blake3, &data);
+        let hashes = [HashType::Blake3, HashType::Md5, HashType::Blake3];
+        let hashed = hash_file(&path, &hashes, &mut new_hashers(&hashes));
+        fs::remove_file(&path).unwrap();
+        let Ok(Hashed::Value(values, size)) = hashed else {
+            panic!("hashing failed");
+        };
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(values[0], expected);
+        assert_eq!(values[1], digest(&HashType::Md5, &data));
+        assert_eq!(values[2], expected);
+    }
+
+    #[test]
+    fn sha2_known_answers() {
+        let cases = [
+            (
+                "sha224",
+                "23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7",
+            ),
+            (
+                "sha384",
+                "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7",
+            ),
+            (
+                "sha512",
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+            (
+                "sha512/256",
+                "53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23",
+            ),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                digest(&HashType::from_str(name).unwrap(), b"abc"),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn sha3_and_shake_known_answers() {
+        let cases = [
+            (
+                "sha3-256",
+                "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+            ),
+            (
+                "sha3-512",
+                "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0",
+            ),
+            (
+                "shake128",
+                "5881092dd818bf5cf8a3ddb793fbcba74097d5c526a6d35f97b83351940f2cc8",
+            ),
+            ("shake128:64", "5881092dd818bf5c"),
+            (
+                "shake256",
+                "483366601360a8771c6863080cc4114d8db44530f8f1e1ee4f94ea37e78b5739d5a15bef186a5386c75744c0527e1faa9f8726e462a12a4feb06bd8801e751e4",
+            ),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                digest(&HashType::from_str(name).unwrap(), b"abc"),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn blake2_known_answers() {
+        let cases = [
+            (
+                "blake2b",
+                "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+            ),
+            (
+                "blake2b:256",
+                "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+            ),
+            (
+                "blake2s",
+                "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982",
+            ),
+        ];
+        for (name, expected) in cases {
+            let hash = HashType::from_str(name).unwrap();
+            let mut hasher = new_hasher(&hash);
+            // Hashed twice to check that finalizing resets the hasher.
+            for _ in 0..2 {
+                hasher.update(b"abc");
+                assert_eq!(hex::encode(hasher.finalize_reset()), expected, "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn checksum_known_answers() {
+        assert_eq!(digest(&HashType::Crc32, b"123456789"), "cbf43926");
+        assert_eq!(digest(&HashType::Crc32c, b"123456789"), "e3069283");
+        // The canonical big-endian forms of the empty input values.
+        assert_eq!(digest(&HashType::Xxh64, b""), "ef46db3751d8e999");
+        assert_eq!(
+            digest(&HashType::Xxh3_128, b""),
+            "99aa06d3014798d86001c324468d497f"
+        );
     }
-    Ok(Hashed::Value(hex::encode(hasher.finalize_reset())))
 }
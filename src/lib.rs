@@ -2,7 +2,9 @@ mod error;
 mod exec;
 mod hashing;
 
-use exec::{Checker, OutFile, Queue, load_check_file, run};
+use exec::{
+    AuditSource, Checker, Filter, OutFile, Patterns, Queue, Traversal, load_check_file, run,
+};
 use std::{
     fmt::Display,
     fs,
@@ -12,6 +14,7 @@ use std::{
 };
 
 pub use error::Error;
+pub use exec::{Attr, AuditCounts, AuditOutcome, Format, SpecialFiles, Symlinks};
 pub use hashing::HashType;
 
 const DEFAULT_OUT: &str = "./hashes.txt";
@@ -38,24 +41,87 @@ where
     }
 }
 
+/// Options only relevant to the create command. Native hashes files record the base, the
+/// traversal filter and the traversal policies, which audit reuses.
+pub struct CreateOptions {
+    /// Hash algorithms, sha256 when empty.
+    pub hashes: Vec<HashType>,
+    /// Path to the hashes file, defaults to `./hashes.txt`.
+    pub output: Option<PathBuf>,
+    pub format: Format,
+    /// Attributes recorded next to the hashes, only supported by the native format.
+    pub attrs: Vec<Attr>,
+    /// Directory the recorded paths are relative to.
+    pub base: Option<PathBuf>,
+    /// Write the entries as soon as they are hashed, instead of sorted by path.
+    pub unsorted: bool,
+    /// Glob patterns of the traversed paths to skip.
+    pub exclude: Vec<String>,
+    /// Glob patterns the traversed files must match.
+    pub include: Vec<String>,
+    /// Also read `.gitignore` files, `.goblinignore` files are always read.
+    pub gitignore: bool,
+    /// How symlinks are handled.
+    pub symlinks: Symlinks,
+    /// Do not cross mount points.
+    pub one_file_system: bool,
+    /// How FIFOs, sockets and device nodes are handled.
+    pub special_files: SpecialFiles,
+    /// Hash block devices like regular files.
+    pub hash_block_devices: bool,
+}
+
 pub fn create(
     input: &[String],
     recursive: bool,
     max_threads: u8,
-    mut hashes: Vec<HashType>,
-    output: Option<PathBuf>,
     empty_dirs: bool,
+    options: CreateOptions,
 ) -> Result<(), Error> {
+    let CreateOptions {
+        mut hashes,
+        output,
+        format,
+        attrs,
+        base,
+        unsorted,
+        exclude,
+        include,
+        gitignore,
+        symlinks,
+        one_file_system,
+        special_files,
+        hash_block_devices,
+    } = options;
     if hashes.is_empty() {
         hashes.push(HashType::Sha256);
     }
     let path = output.unwrap_or_else(|| PathBuf::from(DEFAULT_OUT));
-    let outfile = OutFile::new(&path, &hashes)?;
-    let queue = Queue::new(input, recursive)?;
+    let patterns = Patterns {
+        exclude,
+        include,
+        ignore_files: vec![],
+    };
+    let filter = Filter::new(patterns.with_ignore_files(gitignore))?;
+    let queue = Queue::new(
+        input,
+        recursive,
+        base.as_deref(),
+        filter,
+        Traversal {
+            symlinks,
+            one_file_system,
+            special_files,
+            hash_block_devices,
+        },
+    )?;
+    let outfile = OutFile::new(
+        &path, &hashes, format, &attrs, &queue, empty_dirs, !unsorted,
+    )?;
     let result = thread::scope(|s| {
         let mut handles = Vec::with_capacity(max_threads as usize);
         while handles.len() < max_threads as usize {
-            handles.push(s.spawn(|| run(&hashes, &queue, empty_dirs, outfile.writer())));
+            handles.push(s.spawn(|| run(&hashes, &queue, empty_dirs, &outfile)));
         }
         handles
             .into_iter()
@@ -74,39 +140,119 @@ pub fn create(
     result
 }
 
+/// Options only relevant to the audit command.
+pub struct AuditOptions {
+    /// Path to the hashes file, defaults to `./hashes.txt`.
+    pub hashes_file: Option<PathBuf>,
+    /// Hash algorithm of a coreutils hashes file, inferred from its digests when `None`.
+    pub algo: Option<HashType>,
+    /// Stop on the first audit error.
+    pub early: bool,
+    /// Report audit errors and the final result as JSON lines.
+    pub json: bool,
+    /// Report files whose size differs from the hashes file without hashing them. The whole
+    /// hashes file is loaded up front, it must record sizes.
+    pub size_first: bool,
+    /// Like `size_first`, also skipping files whose size and modification time are unchanged.
+    /// The hashes file must record both.
+    pub quick: bool,
+    /// Directory the hashes file paths are relative to, defaults to the one it records.
+    pub base: Option<PathBuf>,
+    /// Glob patterns of the traversed paths to skip. With no `exclude` nor `include`
+    /// patterns, those recorded in the hashes file are used.
+    pub exclude: Vec<String>,
+    /// Glob patterns the traversed files must match.
+    pub include: Vec<String>,
+    /// Also read `.gitignore` files, besides the ignore files recorded in the hashes file.
+    pub gitignore: bool,
+    /// How symlinks are handled, defaults to the policy the hashes file records.
+    pub symlinks: Option<Symlinks>,
+    /// Do not cross mount points, also enabled if the hashes file records it.
+    pub one_file_system: bool,
+    /// How FIFOs, sockets and device nodes are handled, defaults to the policy the hashes
+    /// file records.
+    pub special_files: Option<SpecialFiles>,
+    /// Hash block devices like regular files, also enabled if the hashes file records it.
+    pub hash_block_devices: bool,
+}
+
 pub fn audit(
     input: &[String],
     recursive: bool,
     max_threads: u8,
-    hashes_file: Option<PathBuf>,
-    early: bool,
     empty_dirs: bool,
-) -> Result<(), Error> {
-    let (reader, hashes) = load_check_file(hashes_file)?;
-    let queue = Queue::new(input, recursive)?;
-    let audit_err = thread::scope(|s| {
+    options: AuditOptions,
+) -> Result<AuditOutcome, Error> {
+    let AuditOptions {
+        hashes_file,
+        algo,
+        early,
+        json,
+        size_first,
+        quick,
+        base,
+        exclude,
+        include,
+        gitignore,
+        symlinks,
+        one_file_system,
+        special_files,
+        hash_block_devices,
+    } = options;
+    let (reader, hashes) = load_check_file(hashes_file, algo)?;
+    let base = base.or_else(|| reader.base().map(PathBuf::from));
+    let patterns = match exclude.is_empty() && include.is_empty() {
+        true => reader.patterns().clone(),
+        false => Patterns {
+            exclude,
+            include,
+            ignore_files: reader.patterns().ignore_files.clone(),
+        },
+    };
+    let filter = Filter::new(patterns.with_ignore_files(gitignore))?;
+    let symlinks = symlinks.or(reader.symlinks()).unwrap_or_default();
+    let one_file_system = one_file_system || reader.one_file_system();
+    let special_files = special_files.or(reader.special_files()).unwrap_or_default();
+    let hash_block_devices = hash_block_devices || reader.hash_block_devices();
+    let queue = Queue::new(
+        input,
+        recursive,
+        base.as_deref(),
+        filter,
+        Traversal {
+            symlinks,
+            one_file_system,
+            special_files,
+            hash_block_devices,
+        },
+    )?;
+    let (sender, receiver) = mpsc::channel();
+    let mut checker = Checker::new(reader, receiver, early, empty_dirs, json);
+    let prefilter = match size_first || quick {
+        true => Some(checker.prefilter(quick)?),
+        false => None,
+    };
+    let outcome = thread::scope(|s| {
         let mut handles = Vec::with_capacity(max_threads as usize);
-        let mut checker = {
-            let (sender, receiver) = mpsc::channel();
-            let checker = Checker::new(reader, receiver, early, empty_dirs);
-            while handles.len() < max_threads as usize {
-                let sender = sender.clone();
-                handles.push(s.spawn(|| run(&hashes, &queue, empty_dirs, sender)));
-            }
-            checker
-        };
-        let audit_err = checker.check()?;
+        while handles.len() < max_threads as usize {
+            let source = AuditSource::new(sender.clone(), prefilter.as_ref());
+            handles.push(s.spawn(|| run(&hashes, &queue, empty_dirs, source)));
+        }
+        drop(sender);
+        let outcome = checker.check()?;
         let err = handles
             .into_iter()
             .map(|handle| handle.join().unwrap())
             .find(|result| result.is_err());
         match err {
             Some(result) => Err(result.err().unwrap()),
-            None => Ok(audit_err),
+            None => Ok(outcome),
         }
     })?;
-    if !audit_err {
+    if json {
+        println!("{}", outcome.to_json());
+    } else if let AuditOutcome::Clean = outcome {
         println!("ok");
     }
-    Ok(())
+    Ok(outcome)
 }
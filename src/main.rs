@@ -1,7 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, process::ExitCode};
 
 use gumdrop::Options;
-use hashgoblin::{Error, HashType, audit, create, verbose_init};
+use hashgoblin::{
+    Attr, AuditOptions, AuditOutcome, CreateOptions, Format, HashType, SpecialFiles, Symlinks,
+    audit, create, verbose_init,
+};
+
+/// The audit found at least one missing, extra, mismatched or changed path.
+const EXIT_DISCREPANCIES: u8 = 1;
+/// The program could not complete the requested operation.
+const EXIT_ERROR: u8 = 2;
 
 #[derive(Options)]
 struct Args {
@@ -29,12 +37,66 @@ struct CreateOpts {
     )]
     source: Vec<String>,
     #[options(
-        help = "hash algorithm, suported: sha256, tiger, whirlpool, sha1, md5, default: sha256",
+        help = "hash algorithm, suported: sha224, sha256, sha384, sha512, sha512-256, sha3-256, sha3-512, shake128[:BITS], shake256[:BITS], blake3, blake2b[:BITS], blake2s, tiger, whirlpool, sha1, md5, non-cryptographic checksums (corruption detection only): crc32, crc32c, xxh64, xxh3-128, default: sha256",
         short = "H"
     )]
     hash: Vec<HashType>,
     #[options(help = "path to the output file, default: ./hashes.txt")]
     output: Option<PathBuf>,
+    #[options(
+        help = "output file format, suported: native, coreutils (sha256sum style, single algorithm), bsd (SHA256 (path) = hash), hashdeep (md5, sha1, sha256, tiger and whirlpool only), dfxml (cannot be audited), jsonl, csv or csv:<column>,... with columns path, size, mtime, hashes (all algorithms) or single algorithms, default: native",
+        short = "F"
+    )]
+    format: Option<Format>,
+    #[options(
+        help = "attribute recorded next to the hashes of every entry and checked by audit, native format only, suported: size, mtime, ctime, mode, uid, gid, type, link (hardlink sets, checked as a whole)",
+        short = "A"
+    )]
+    attr: Vec<Attr>,
+    #[options(
+        help = "record paths relative to this directory, every source must be inside it and it is the source when none is given"
+    )]
+    base: Option<PathBuf>,
+    #[options(
+        help = "write entries in the order they are hashed instead of sorted by path, the default sorting keeps up to 64 MiB of entries in memory and spills the rest to temporary files next to the output",
+        no_short
+    )]
+    unsorted: bool,
+    #[options(
+        help = "glob pattern of paths to skip while traversing directories, e.g. **/.git/**, *.tmp or Thumbs.db. Patterns without a / match file names",
+        short = "x"
+    )]
+    exclude: Vec<String>,
+    #[options(
+        help = "glob pattern that traversed files must match to be hashed, directories are always traversed",
+        short = "i"
+    )]
+    include: Vec<String>,
+    #[options(
+        help = "besides .goblinignore files, also skip the paths ignored by .gitignore files in the traversed directories",
+        no_short
+    )]
+    gitignore: bool,
+    #[options(
+        help = "symlink policy, input paths included: follow (hash the targets, skipping directories that loop back), skip or record (the target path, native format only), default: follow",
+        no_short
+    )]
+    symlinks: Option<Symlinks>,
+    #[options(
+        help = "do not descend into directories on another filesystem than their source, such as /proc or network and bind mounts",
+        no_short
+    )]
+    one_file_system: bool,
+    #[options(
+        help = "policy for FIFOs, sockets and device nodes, which are never read: skip (with a warning) or record (their type, native format only), default: skip",
+        no_short
+    )]
+    special_files: Option<SpecialFiles>,
+    #[options(
+        help = "hash the content of block devices like regular files instead of applying the special files policy",
+        no_short
+    )]
+    hash_block_devices: bool,
 }
 
 #[derive(Options)]
@@ -46,8 +108,70 @@ struct AuditOpts {
     source: Vec<String>,
     #[options(help = "exit early on the first audit mismatch", short = "E")]
     early: bool,
-    #[options(help = "path to the hashes file, default ./hashes.txt", short = "f")]
+    #[options(
+        help = "path to the hashes file, default ./hashes.txt. Files produced by GNU coreutils tools such as sha256sum, with or without --tag, by hashdeep, jsonl and csv files are also accepted",
+        short = "f"
+    )]
     hashes_file: Option<PathBuf>,
+    #[options(
+        help = "hash algorithm of a coreutils hashes file, inferred from the digest length when omitted",
+        short = "a"
+    )]
+    algo: Option<HashType>,
+    #[options(
+        help = "print audit errors as JSON lines followed by a summary object, instead of text",
+        no_short
+    )]
+    json: bool,
+    #[options(
+        help = "report files whose size differs from the hashes file without hashing them, the hashes file must record sizes (native size attribute, hashdeep, jsonl or csv) and is loaded in memory",
+        no_short
+    )]
+    size_first: bool,
+    #[options(
+        help = "like --size-first, also skipping files whose size and modification time are unchanged, the hashes file must record both (native size and mtime attributes or csv with the mtime column)",
+        no_short
+    )]
+    quick: bool,
+    #[options(
+        help = "directory the hashes file paths are relative to, re-anchoring them to a new root. Defaults to the base recorded by create, it is the source when none is given"
+    )]
+    base: Option<PathBuf>,
+    #[options(
+        help = "glob pattern of paths to skip while traversing directories, replacing the patterns recorded by create",
+        short = "x"
+    )]
+    exclude: Vec<String>,
+    #[options(
+        help = "glob pattern that traversed files must match to be audited, replacing the patterns recorded by create",
+        short = "i"
+    )]
+    include: Vec<String>,
+    #[options(
+        help = "also skip the paths ignored by .gitignore files, besides the ignore files recorded by create",
+        no_short
+    )]
+    gitignore: bool,
+    #[options(
+        help = "symlink policy: follow, skip or record, default: the one recorded by create, follow if none",
+        no_short
+    )]
+    symlinks: Option<Symlinks>,
+    #[options(
+        help = "do not descend into directories on another filesystem than their source, always enabled if create recorded it",
+        no_short
+    )]
+    one_file_system: bool,
+    #[options(
+        help = "policy for FIFOs, sockets and device nodes: skip or record, default: the one recorded by create, skip if none",
+        no_short
+    )]
+    special_files: Option<SpecialFiles>,
+    #[options(
+        help = "hash the content of block devices like regular files, always enabled if create recorded it",
+        no_short
+    )]
+    hash_block_devices: bool,
 }
 
 #[derive(Options)]
@@ -60,38 +184,78 @@ struct HelpOpts {
 enum Command {
     #[options(help = "show help for a command")]
     Help(HelpOpts),
-    #[options(help = "create a new hashes file that can be audited later with the audit command")]
+    #[options(
+        help = "create a new hashes file that can be audited later with the audit command. Native hashes files record the base, the glob patterns, the ignore files and the traversal policies, which audit reuses"
+    )]
     Create(CreateOpts),
-    #[options(help = "audit a source directory or files against a hashes file")]
+    #[options(
+        help = "audit a source directory or files against a hashes file, exits with 0 if the audit is clean, 1 if discrepancies were found and 2 on errors"
+    )]
     Audit(AuditOpts),
 }
 
-fn main() -> Result<(), Error> {
+fn main() -> ExitCode {
     let args = Args::parse_args_default_or_exit();
     verbose_init(args.verbose);
-    match args.command {
+    let result = match args.command {
         Some(Command::Create(opts)) => create(
             &opts.source,
             args.recursive,
             args.max_threads.unwrap_or(5),
-            opts.hash,
-            opts.output,
             args.empty_dirs,
-        ),
+            CreateOptions {
+                hashes: opts.hash,
+                output: opts.output,
+                format: opts.format.unwrap_or_default(),
+                attrs: opts.attr,
+                base: opts.base,
+                unsorted: opts.unsorted,
+                exclude: opts.exclude,
+                include: opts.include,
+                gitignore: opts.gitignore,
+                symlinks: opts.symlinks.unwrap_or_default(),
+                one_file_system: opts.one_file_system,
+                special_files: opts.special_files.unwrap_or_default(),
+                hash_block_devices: opts.hash_block_devices,
+            },
+        )
+        .map(|_| ExitCode::SUCCESS),
         Some(Command::Audit(opts)) => audit(
             &opts.source,
             args.recursive,
             args.max_threads.unwrap_or(5),
-            opts.hashes_file,
-            opts.early,
             args.empty_dirs,
-        ),
+            AuditOptions {
+                hashes_file: opts.hashes_file,
+                algo: opts.algo,
+                early: opts.early,
+                json: opts.json,
+                size_first: opts.size_first,
+                quick: opts.quick,
+                base: opts.base,
+                exclude: opts.exclude,
+                include: opts.include,
+                gitignore: opts.gitignore,
+                symlinks: opts.symlinks,
+                one_file_system: opts.one_file_system,
+                special_files: opts.special_files,
+                hash_block_devices: opts.hash_block_devices,
+            },
+        )
+        .map(|outcome| match outcome {
+            AuditOutcome::Clean => ExitCode::SUCCESS,
+            AuditOutcome::Discrepancies(_) => ExitCode::from(EXIT_DISCREPANCIES),
+        }),
         None => {
             println!("You must specify a command, use --help [COMMAND] for more information\n");
             println!("{}\n", args.self_usage());
             println!("Available Commands:\n{}", args.self_command_list().unwrap());
-            Ok(())
+            Ok(ExitCode::SUCCESS)
         }
-        _ => Ok(()),
-    }
+        _ => Ok(ExitCode::SUCCESS),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("Error: {err}");
+        ExitCode::from(EXIT_ERROR)
+    })
 }